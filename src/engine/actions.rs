@@ -1,16 +1,16 @@
-use std::f32::consts::PI;
+use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::*;
-use bevy::sprite::Mesh2dHandle;
 
+use crate::engine::board::{turn_due, Turn};
 use crate::engine::common::*;
-use crate::engine::random::random_player_action;
+use crate::engine::config::SimConfig;
+use crate::engine::random::SimRng;
+use crate::engine::report::{DeathCause, DeathEvent, GenerationReport};
+use crate::engine::scent::{ScentField, ScentLayer};
+use crate::simulation::genome::{Genome, Memory, Situation};
 use crate::simulation::players::*;
 
-use super::config::{
-    action_cost, default_entity_size, DEFAULT_COLOR_ON_LOS_DETECT, DEFAULT_WALL_COLOR,
-};
-
 #[derive(Event, Debug)]
 pub struct KillEvent {
     pub killer_id: Entity,
@@ -43,22 +43,31 @@ pub struct BuildWallEvent {
     pub builder_id: Entity,
 }
 
+#[derive(Event, Debug)]
+pub struct DepositPheromoneEvent {
+    pub depositor_id: Entity,
+}
+
 #[derive(Event, Debug)]
 pub struct ScanLOSEvent {
     pub scanner_id: Entity,
     pub scanner_facing: FacingDirection,
 }
 
+/// Every tile a scan covered, whether there was anything on it or not.
 #[derive(Event, Debug)]
-pub struct LOSReportEvent {
-    pub scanner_id: Entity,
-    pub scanned_type: OccupantType,
+pub struct LOSScannedEvent {
+    pub tiles: Vec<BoardPosition>,
 }
 
 #[derive(Event, Debug)]
-pub struct RestoreColorsEvent {
-    pub entity_id: Entity,
-    pub old_color: Color,
+pub struct LOSReportEvent {
+    pub scanner_id: Entity,
+    pub scanned_type: OccupantType,
+    pub scanned_pos: BoardPosition,
+    /// How many tiles away from the scanner it is, diagonal steps included.
+    pub distance: u32,
+    pub turn: u32,
 }
 
 #[derive(Event, Debug)]
@@ -66,98 +75,206 @@ pub struct UpdateVitalsEvent {
     pub hungerer_id: Entity,
 }
 
-fn move_player(
-    board: &mut Board,
-    mover_id: Entity,
-    mover_pos: &BoardPosition,
-    mover_facing: &FacingDirection,
-    movement_direction: &FacingDirection,
-    player_query: &mut Query<
-        (&mut BoardPosition, &mut PlayerActionType, &mut Transform),
-        (With<Player>, Without<BoardTile>),
-    >,
-) -> bool {
-    let movement_fn = match movement_direction {
-        FacingDirection::Up => Board::looking_at,
-        FacingDirection::Down => Board::disengage_to,
-        _ => unreachable!("ERROR: ILLEGAL MOVE: can only move forwards or backwards."),
-    };
-    let movement_fn_mut = match movement_direction {
-        FacingDirection::Up => Board::looking_at_mut,
-        FacingDirection::Down => Board::disengage_to_mut,
-        _ => unreachable!("ERROR: ILLEGAL MOVE (MUT): can only move forwards or backwards."),
+/// The outcome of every contested action of the current turn.
+/// All intents are judged against the board as it was when the turn started,
+/// so the result doesn't depend on the order in which the players are processed:
+/// - all attacks land at the same time, so two players finishing each other off both die,
+///   and anything a killed player meant to do this turn doesn't happen,
+/// - a victim facing its attacker hits back, whatever it's doing itself,
+/// - moves and walls targeting the same tile all fail,
+/// - food that several players try to eat is split evenly between them (the remainder is lost).
+#[derive(Resource, Default, Debug)]
+struct TurnResolution {
+    /// Attacker -> victim.
+    attacks: HashMap<Entity, Entity>,
+    /// Victim, attacker and the damage done, counter-attacks included.
+    wounds: Vec<(Entity, Entity, u32)>,
+    /// Victim and killer, the one that did it the most damage this turn, in the order the victims were first hit.
+    deaths: Vec<(Entity, Entity)>,
+    victims: HashSet<Entity>,
+    /// Mover -> destination.
+    moves: HashMap<Entity, BoardPosition>,
+    /// Gorger -> the food it eats and its share of the food's energy.
+    meals: HashMap<Entity, (Entity, u32)>,
+    /// Builder -> where the wall goes.
+    walls: HashMap<Entity, BoardPosition>,
+}
+
+fn resolve_player_intents(
+    mut resolution: ResMut<TurnResolution>,
+    mut kill_events: EventReader<KillEvent>,
+    mut eat_events: EventReader<EatEvent>,
+    mut move_events: EventReader<MoveEvent>,
+    mut build_wall_events: EventReader<BuildWallEvent>,
+    board: Res<Board>,
+    config: Res<SimConfig>,
+    player_query: Query<(&BoardPosition, &FacingDirection, &Vitals, &Genome), With<Player>>,
+    food_query: Query<&Energy, (With<Food>, Without<Player>)>,
+) {
+    *resolution = TurnResolution::default();
+
+    let combat = &config.combat;
+    let damage = |vitals: &Vitals, genome: &Genome| {
+        combat.damage(vitals.energy.value, genome.strength.unwrap_or(1.0))
     };
+    for event in kill_events.read() {
+        let Ok((killer_pos, _, killer_vitals, killer_genome)) = player_query.get(event.killer_id)
+        else {
+            continue;
+        };
+        let Some((_, OccupantType::Player(victim_id))) =
+            board.looking_at(killer_pos, &event.killer_facing)
+        else {
+            continue;
+        };
+        let Ok((victim_pos, victim_facing, victim_vitals, victim_genome)) =
+            player_query.get(*victim_id)
+        else {
+            continue;
+        };
+        if victim_vitals.status != PlayerStatus::Alive {
+            continue;
+        }
+        resolution.attacks.insert(event.killer_id, *victim_id);
+        resolution.wounds.push((
+            *victim_id,
+            event.killer_id,
+            damage(killer_vitals, killer_genome),
+        ));
+        if board.looking_at(victim_pos, victim_facing)
+            == Some((*killer_pos, &OccupantType::Player(event.killer_id)))
+        {
+            let counter_damage =
+                damage(victim_vitals, victim_genome) as f32 * combat.counter_damage;
+            resolution
+                .wounds
+                .push((event.killer_id, *victim_id, counter_damage as u32));
+        }
+    }
+    let mut damage_taken: HashMap<Entity, HashMap<Entity, u32>> = HashMap::new();
+    for (victim_id, attacker_id, damage) in resolution.wounds.iter() {
+        *damage_taken
+            .entry(*victim_id)
+            .or_default()
+            .entry(*attacker_id)
+            .or_default() += damage;
+    }
+    let mut deaths = vec![];
+    for (victim_id, _, _) in resolution.wounds.iter() {
+        let Some(attackers) = damage_taken.remove(victim_id) else {
+            continue;
+        };
+        let Ok((_, _, victim_vitals, _)) = player_query.get(*victim_id) else {
+            continue;
+        };
+        if attackers.values().sum::<u32>() < victim_vitals.health.value {
+            continue;
+        }
+        // ties go to whoever struck first
+        let killer_id = resolution
+            .wounds
+            .iter()
+            .rev()
+            .filter(|(victim, _, _)| victim == victim_id)
+            .max_by_key(|(_, attacker, _)| attackers[attacker])
+            .map(|(_, attacker, _)| *attacker)
+            .unwrap();
+        deaths.push((*victim_id, killer_id));
+    }
+    resolution.victims = deaths.iter().map(|(victim_id, _)| *victim_id).collect();
+    resolution.deaths = deaths;
 
-    let mut maybe_move_data: Option<(BoardPosition, OccupantType)> = None;
-    if let Some((new_pos, new_tile_occ)) = movement_fn(board, mover_pos, mover_facing) {
-        if let Some(old_tile_occ) = board.occ_at(mover_pos) {
-            if *new_tile_occ == OccupantType::Empty {
-                // get data necessary for the move via immutable queries
-                maybe_move_data = Some((new_pos, *old_tile_occ));
+    // Moves and walls both claim an empty tile, so they contest each other.
+    let mut claims: Vec<(Entity, BoardPosition, bool)> = vec![];
+    for event in move_events.read() {
+        if resolution.victims.contains(&event.mover_id) {
+            continue;
+        }
+        let destination = match event.movement_direction {
+            FacingDirection::Up => board.looking_at(&event.mover_pos, &event.mover_facing),
+            FacingDirection::Down => board.disengage_to(&event.mover_pos, &event.mover_facing),
+            _ => unreachable!("ERROR: ILLEGAL MOVE: can only move forwards or backwards."),
+        };
+        if let Some((new_pos, OccupantType::Empty)) = destination {
+            claims.push((event.mover_id, new_pos, true));
+        }
+    }
+    for event in build_wall_events.read() {
+        if resolution.victims.contains(&event.builder_id) {
+            continue;
+        }
+        if let Ok((builder_pos, builder_facing, _, _)) = player_query.get(event.builder_id) {
+            if let Some((wall_pos, OccupantType::Empty)) =
+                board.looking_at(builder_pos, builder_facing)
+            {
+                claims.push((event.builder_id, wall_pos, false));
             }
         }
     }
-
-    if maybe_move_data.is_some() {
-        if let Some(old_occ_mut) = board.occ_at_mut(mover_pos) {
-            *old_occ_mut = OccupantType::Empty; // deoccupy the old tile if the move is valid
+    let mut claims_per_tile: HashMap<BoardPosition, u32> = HashMap::new();
+    for (_, pos, _) in claims.iter() {
+        *claims_per_tile.entry(*pos).or_default() += 1;
+    }
+    for (claimer_id, pos, is_move) in claims {
+        if claims_per_tile[&pos] > 1 {
+            continue;
         }
+        match is_move {
+            true => resolution.moves.insert(claimer_id, pos),
+            false => resolution.walls.insert(claimer_id, pos),
+        };
     }
 
-    let mut move_succeeded = false;
-    if let Some((new_pos, old_occ_clone)) = maybe_move_data {
-        if let Ok((mut mover_pos, mut last_action, mut mover_transform)) =
-            player_query.get_mut(mover_id)
-        {
-            if let Some((_, new_tile_occ)) = movement_fn_mut(board, &mover_pos, mover_facing) {
-                // move player occupancy to the new position
-                *new_tile_occ = old_occ_clone;
-
-                // move player graphics (transform)
-                mover_transform.translation =
-                    Vec3::new(grid_to_world(new_pos.x), grid_to_world(new_pos.y), 0.1);
-
-                // update new player board position
-                *mover_pos = BoardPosition {
-                    x: new_pos.x,
-                    y: new_pos.y,
-                };
-
-                // log last action taken for action cost calculation
-                *last_action = PlayerActionType::MoveForward;
-                move_succeeded = true;
+    let mut gorgers: Vec<(Entity, Entity)> = vec![];
+    for event in eat_events.read() {
+        if resolution.victims.contains(&event.gorger_id) {
+            continue;
+        }
+        if let Ok((gorger_pos, _, _, _)) = player_query.get(event.gorger_id) {
+            if let Some((_, OccupantType::Food(food_id))) =
+                board.looking_at(gorger_pos, &event.gorger_facing)
+            {
+                gorgers.push((event.gorger_id, *food_id));
             }
         }
     }
-
-    move_succeeded
+    let mut gorgers_per_food: HashMap<Entity, u32> = HashMap::new();
+    for (_, food_id) in gorgers.iter() {
+        *gorgers_per_food.entry(*food_id).or_default() += 1;
+    }
+    for (gorger_id, food_id) in gorgers {
+        if let Ok(food_energy) = food_query.get(food_id) {
+            let share = food_energy.value / gorgers_per_food[&food_id];
+            resolution.meals.insert(gorger_id, (food_id, share));
+        }
+    }
 }
 
 fn player_move_listener(
     mut move_events: EventReader<MoveEvent>,
-    mut player_query: Query<
-        (&mut BoardPosition, &mut PlayerActionType, &mut Transform),
-        (With<Player>, Without<BoardTile>),
-    >,
+    mut player_query: Query<(&mut BoardPosition, &mut PlayerActionType), With<Player>>,
     mut board: ResMut<Board>,
+    resolution: Res<TurnResolution>,
 ) {
     for event in move_events.read() {
-        if event.movement_direction != FacingDirection::Up {
+        let Ok((mut mover_pos, mut last_action)) = player_query.get_mut(event.mover_id) else {
             continue;
-        }
-        let ok = move_player(
-            &mut board,
-            event.mover_id,
-            &event.mover_pos,
-            &event.mover_facing,
-            &FacingDirection::Up,
-            &mut player_query,
-        );
-        if let Ok((_, mut last_action, _)) = player_query.get_mut(event.mover_id) {
-            match ok {
-                true => *last_action = PlayerActionType::MoveForward,
-                false => *last_action = PlayerActionType::Idle,
+        };
+        *last_action = PlayerActionType::Idle;
+        if let Some(new_pos) = resolution.moves.get(&event.mover_id) {
+            if let Some(old_occ) = board.occ_at_mut(&mover_pos) {
+                *old_occ = OccupantType::Empty;
+            }
+            if let Some(new_occ) = board.occ_at_mut(new_pos) {
+                *new_occ = OccupantType::Player(event.mover_id);
             }
+            *mover_pos = *new_pos;
+
+            // log last action taken for action cost calculation
+            *last_action = match event.movement_direction {
+                FacingDirection::Down => PlayerActionType::MoveBackwards,
+                _ => PlayerActionType::MoveForward,
+            };
         }
     }
 }
@@ -166,25 +283,33 @@ fn player_eat_listener(
     mut commands: Commands,
     mut eat_events: EventReader<EatEvent>,
     mut board: ResMut<Board>,
+    resolution: Res<TurnResolution>,
+    mut report: ResMut<GenerationReport>,
     mut player_query: Query<
-        (&BoardPosition, &mut PlayerActionType, &mut Vitals),
+        (&mut PlayerActionType, &mut Vitals, &mut PlayerStats),
         (With<Player>, Without<Food>),
     >,
-    food_query: Query<&Energy, (With<Food>, Without<Player>)>,
+    food_query: Query<&BoardPosition, (With<Food>, Without<Player>)>,
 ) {
     for event in eat_events.read() {
-        if let Ok((gorger_pos, mut last_action, mut gorger_vitals)) =
+        let Ok((mut last_action, mut gorger_vitals, mut gorger_stats)) =
             player_query.get_mut(event.gorger_id)
-        {
-            *last_action = PlayerActionType::Idle;
-            if let Some((_, occ)) = board.looking_at_mut(gorger_pos, &event.gorger_facing) {
-                if let OccupantType::Food(food_id) = *occ {
-                    if let Ok(food_energy) = food_query.get(food_id) {
-                        gorger_vitals.energy.value += food_energy.value;
-                        commands.entity(food_id).despawn_recursive();
+        else {
+            continue;
+        };
+        *last_action = PlayerActionType::Idle;
+        if let Some((food_id, share)) = resolution.meals.get(&event.gorger_id) {
+            gorger_vitals.energy.value += share;
+            gorger_stats.food_eaten += 1;
+            *last_action = PlayerActionType::Eat;
+
+            // the food goes away with its first gorger, the others only get their share
+            if let Ok(food_pos) = food_query.get(*food_id) {
+                if let Some(occ) = board.occ_at_mut(food_pos) {
+                    if *occ == OccupantType::Food(*food_id) {
+                        commands.entity(*food_id).despawn_recursive();
                         *occ = OccupantType::Empty;
-
-                        *last_action = PlayerActionType::Eat;
+                        report.food_eaten += 1;
                     }
                 }
             }
@@ -192,105 +317,179 @@ fn player_eat_listener(
     }
 }
 
+/// Take a dead player off the board, leaving its body behind as food if there's anything to eat on it.
+fn remove_dead_player(
+    commands: &mut Commands,
+    board: &mut ResMut<Board>,
+    config: &SimConfig,
+    player_id: Entity,
+    pos: BoardPosition,
+    corpse_energy: u32,
+) {
+    if let Some(occ) = board.occ_at_mut(&pos) {
+        *occ = OccupantType::Empty;
+    }
+    commands.entity(player_id).despawn_recursive();
+    if corpse_energy == 0 {
+        return;
+    }
+    if let Err(e) = place_food_at(
+        commands,
+        pos,
+        FoodType::DeadMeat(corpse_energy),
+        config,
+        board,
+    ) {
+        warn!("Tried to place a dead body, but failed: `{}`", e);
+    }
+}
+
 fn update_vitals_listener(
+    mut commands: Commands,
     mut uv_events: EventReader<UpdateVitalsEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut board: ResMut<Board>,
     mut player_query: Query<
-        (&mut Vitals, &mut Handle<ColorMaterial>, &PlayerActionType),
-        (With<Player>, Without<BoardTile>),
+        (
+            &mut Vitals,
+            &mut PlayerStats,
+            &PlayerActionType,
+            &BoardPosition,
+            &LineOfSight,
+            &Genome,
+        ),
+        With<Player>,
     >,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    config: Res<SimConfig>,
+    turn: Res<Turn>,
+    mut report: ResMut<GenerationReport>,
 ) {
     for event in uv_events.read() {
-        if let Ok((mut hungerer_vitals, mut hungerer_color, last_action)) =
-            player_query.get_mut(event.hungerer_id)
+        if let Ok((
+            mut hungerer_vitals,
+            mut hungerer_stats,
+            last_action,
+            hungerer_pos,
+            los,
+            genome,
+        )) = player_query.get_mut(event.hungerer_id)
         {
-            hungerer_vitals.energy.value = hungerer_vitals
-                .energy
-                .value
-                .saturating_sub(action_cost(last_action));
-            if hungerer_vitals.energy.value == 0 {
+            hungerer_vitals.energy.value = hungerer_vitals.energy.value.saturating_sub(
+                config
+                    .action_cost(last_action, los.shape)
+                    .saturating_add(config.sight_upkeep(los.shape)),
+            );
+            hungerer_stats.energy = hungerer_vitals.energy.value;
+            // every player that acted this turn gets here exactly once, with the action it ended up taking
+            *report.actions.entry(*last_action).or_default() += 1;
+            if let Some(index) = PlayerActionType::ALL.iter().position(|a| a == last_action) {
+                hungerer_stats.actions[index] += 1;
+            }
+            if hungerer_vitals.energy.value == 0 && hungerer_vitals.status == PlayerStatus::Alive {
                 hungerer_vitals.status = PlayerStatus::DedPepega;
-                *hungerer_color = materials.add(Color::srgb(0., 0., 0.));
+                death_events.send(DeathEvent {
+                    player_id: event.hungerer_id,
+                    cause: DeathCause::Starvation,
+                    turn: turn.num,
+                    pos: *hungerer_pos,
+                    stats: *hungerer_stats,
+                    genome: genome.clone(),
+                });
+                remove_dead_player(
+                    &mut commands,
+                    &mut board,
+                    &config,
+                    event.hungerer_id,
+                    *hungerer_pos,
+                    config.starved_corpse_value,
+                );
+            } else {
+                hungerer_stats.turns_survived += 1;
             }
         }
     }
 }
 
+/// Attacks wear the victims' health down, and whoever runs out of it dies and leaves its energy behind as a corpse.
 fn player_kill_listener(
     mut kill_event: EventReader<KillEvent>,
     mut commands: Commands,
     mut board: ResMut<Board>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
+    resolution: Res<TurnResolution>,
+    config: Res<SimConfig>,
+    turn: Res<Turn>,
+    mut death_events: EventWriter<DeathEvent>,
     mut player_query: Query<
         (
             &BoardPosition,
             &mut Vitals,
             &mut PlayerActionType,
-            &mut Handle<ColorMaterial>,
-            &mut Mesh2dHandle,
+            &mut PlayerStats,
+            &Genome,
         ),
         (With<Player>, Without<Food>),
     >,
 ) {
     for event in kill_event.read() {
-        let mut kill_succeeded = false;
-        if let Ok((killer_pos, _, _, _, _)) = player_query.get(event.killer_id) {
-            if let Some((_, victim_tile_occ)) =
-                board.looking_at_mut(killer_pos, &event.killer_facing)
-            {
-                if let OccupantType::Player(victim_id) = *victim_tile_occ {
-                    if let Ok((victim_pos, victim_vitals, _, _, _)) =
-                        player_query.get_mut(victim_id)
-                    {
-                        if victim_vitals.status == PlayerStatus::Alive {
-                            *victim_tile_occ = OccupantType::Empty;
-                            commands.entity(victim_id).despawn_recursive();
-                            if let Err(e) = place_food_at(
-                                &mut commands,
-                                *victim_pos,
-                                FoodType::DeadMeat(victim_vitals.energy.value),
-                                &mut board,
-                                &mut materials,
-                                &mut meshes,
-                            ) {
-                                warn!("Tried to place a dead body, but failed: `{}`", e);
-                            }
-                            kill_succeeded = true;
-                        }
-                    }
-                }
-            }
-        }
         if let Ok((_, _, mut last_killer_action, _, _)) = player_query.get_mut(event.killer_id) {
-            *last_killer_action = match kill_succeeded {
+            *last_killer_action = match resolution.attacks.contains_key(&event.killer_id) {
                 true => PlayerActionType::Kill,
                 false => PlayerActionType::Idle,
-            }
+            };
+        }
+    }
+    for (victim_id, _, damage) in resolution.wounds.iter() {
+        if let Ok((_, mut victim_vitals, _, _, _)) = player_query.get_mut(*victim_id) {
+            victim_vitals.health.value = victim_vitals.health.value.saturating_sub(*damage);
+        }
+    }
+    for (_, killer_id) in resolution.deaths.iter() {
+        if let Ok((_, _, _, mut killer_stats, _)) = player_query.get_mut(*killer_id) {
+            killer_stats.kills += 1;
         }
     }
+    for (victim_id, killer_id) in resolution.deaths.iter() {
+        let Ok((victim_pos, victim_vitals, _, victim_stats, victim_genome)) =
+            player_query.get(*victim_id)
+        else {
+            continue;
+        };
+        let (victim_pos, victim_energy) = (*victim_pos, victim_vitals.energy.value);
+        death_events.send(DeathEvent {
+            player_id: *victim_id,
+            cause: DeathCause::KilledBy(*killer_id),
+            turn: turn.num,
+            pos: victim_pos,
+            stats: PlayerStats {
+                energy: 0,
+                ..*victim_stats
+            },
+            genome: victim_genome.clone(),
+        });
+        remove_dead_player(
+            &mut commands,
+            &mut board,
+            &config,
+            *victim_id,
+            victim_pos,
+            victim_energy,
+        );
+    }
 }
 
 fn player_turn_listener(
     mut turn_events: EventReader<TurnEvent>,
-    mut player_query: Query<
-        (&mut FacingDirection, &mut PlayerActionType, &mut Transform),
-        (With<Player>, Without<BoardTile>),
-    >,
+    resolution: Res<TurnResolution>,
+    mut player_query: Query<(&mut FacingDirection, &mut PlayerActionType), With<Player>>,
 ) {
     for event in turn_events.read() {
-        if let Ok((mut turner_facing_mut, mut last_action, mut turner_transform)) =
-            player_query.get_mut(event.turner_id)
+        if resolution.victims.contains(&event.turner_id) {
+            continue;
+        }
+        if let Ok((mut turner_facing_mut, mut last_action)) = player_query.get_mut(event.turner_id)
         {
-            let turn_rad = match event.turn_direction {
-                FacingDirection::Right => -PI / 2.,
-                FacingDirection::Left => PI / 2.,
-                _ => unreachable!("turn_event_listener(): can ONLY turn left or right!"),
-            };
-
-            *turner_facing_mut =
-                position_after_turn(&event.turner_facing, event.turn_direction).unwrap();
-            turner_transform.rotate_z(turn_rad);
+            *turner_facing_mut = position_after_turn(&event.turner_facing, event.turn_direction)
+                .expect("turn_event_listener(): can ONLY turn left or right!");
             *last_action = PlayerActionType::Turn(event.turn_direction);
         }
     }
@@ -300,59 +499,55 @@ fn player_build_wall_listener(
     mut build_wall_events: EventReader<BuildWallEvent>,
     mut commands: Commands,
     mut board: ResMut<Board>,
+    resolution: Res<TurnResolution>,
+    mut report: ResMut<GenerationReport>,
     mut player_query: Query<
-        (&FacingDirection, &mut PlayerActionType, &BoardPosition),
+        (&mut PlayerActionType, &mut PlayerStats),
         (With<Player>, Without<BoardTile>),
     >,
 ) {
     for event in build_wall_events.read() {
-        let mut maybe_empty: Option<BoardPosition> = None;
         let mut last_action_type: PlayerActionType = PlayerActionType::Idle;
-        if let Ok((builder_facing, _, builder_pos)) = player_query.get(event.builder_id) {
-            if let Some((occ_pos, occ_type)) = board.looking_at(builder_pos, builder_facing) {
-                maybe_empty = if *occ_type == OccupantType::Empty {
-                    Some(occ_pos)
-                } else {
-                    None
-                }
-            }
-        }
-        if let Some(wall_pos) = maybe_empty {
-            if let Some(occ) = board.occ_at_mut(&wall_pos) {
+        if let Some(wall_pos) = resolution.walls.get(&event.builder_id) {
+            if let Some(occ) = board.occ_at_mut(wall_pos) {
                 *occ = OccupantType::Wall(
                     commands
                         .spawn((
                             WallBundle {
-                                board_pos: wall_pos,
-                                sprite: SpriteBundle {
-                                    sprite: Sprite {
-                                        color: DEFAULT_WALL_COLOR,
-                                        custom_size: Some(Vec2::new(
-                                            default_entity_size(),
-                                            default_entity_size(),
-                                        )),
-                                        ..default()
-                                    },
-                                    transform: Transform::from_xyz(
-                                        grid_to_world(wall_pos.x),
-                                        grid_to_world(wall_pos.y),
-                                        0.1,
-                                    ),
-                                    ..Default::default()
-                                },
+                                board_pos: *wall_pos,
                             },
                             Wall,
                         ))
                         .id(),
                 );
                 last_action_type = PlayerActionType::BuildWall;
+                report.walls_built += 1;
             }
-        } else {
-            last_action_type = PlayerActionType::Idle;
         }
 
-        if let Ok((_, mut last_action, _)) = player_query.get_mut(event.builder_id) {
+        if let Ok((mut last_action, mut builder_stats)) = player_query.get_mut(event.builder_id) {
             *last_action = last_action_type;
+            if last_action_type == PlayerActionType::BuildWall {
+                builder_stats.walls_built += 1;
+            }
+        }
+    }
+}
+
+fn player_deposit_pheromone_listener(
+    mut deposit_pheromone_events: EventReader<DepositPheromoneEvent>,
+    mut scents: ResMut<ScentField>,
+    config: Res<SimConfig>,
+    resolution: Res<TurnResolution>,
+    mut player_query: Query<(&BoardPosition, &mut PlayerActionType), With<Player>>,
+) {
+    for event in deposit_pheromone_events.read() {
+        if resolution.victims.contains(&event.depositor_id) {
+            continue;
+        }
+        if let Ok((pos, mut last_action)) = player_query.get_mut(event.depositor_id) {
+            scents.add(ScentLayer::Pheromone, pos, config.scent.pheromone_deposit);
+            *last_action = PlayerActionType::DepositPheromone;
         }
     }
 }
@@ -360,17 +555,26 @@ fn player_build_wall_listener(
 /// What should this system do?
 /// It should scan line of sight of the current player and determine if
 /// there is something within the line of sight.
-/// For now, we're doing simple straight line directly in front of the player,
-/// but in the future there should be more options for different types of LOS, like circle, cone etc.
-/// If there IS something within the player's line of sight, report it via firing a different event. All the systems that need LOS are going to respond with subscribing to those.
+/// What the player sees depends on the shape of its line of sight (a straight line, a cone or a circle),
+/// and walls hide whatever is behind them (see `get_los_tiles`).
+/// If there IS something within the player's line of sight, report it via firing a different event, one per thing seen,
+/// closest first, if the scan mode reports more than the closest one. All the systems that need LOS are going to respond with subscribing to those.
 /// In the new event, we need to basically report OccupantType and BoardPosition of what we're seeing (and don't forget the scanner's Entity ID itself).
+/// The scanner remembers what it saw (see `player_perception_listener`), and the visualizer highlights it.
 fn player_scan_los_listener(
     mut scanlos_events: EventReader<ScanLOSEvent>,
     mut losreport_events: EventWriter<LOSReportEvent>,
+    mut losscanned_events: EventWriter<LOSScannedEvent>,
     board: Res<Board>,
+    config: Res<SimConfig>,
+    turn: Res<Turn>,
+    resolution: Res<TurnResolution>,
     mut player_query: Query<(&BoardPosition, &mut PlayerActionType, &LineOfSight), With<Player>>,
 ) {
     for event in scanlos_events.read() {
+        if resolution.victims.contains(&event.scanner_id) {
+            continue;
+        }
         let maybe_scanner = if let Ok((pos, _, los)) = player_query.get(event.scanner_id) {
             Some((*pos, *los))
         } else {
@@ -379,17 +583,20 @@ fn player_scan_los_listener(
         if let Some((pos, los)) = maybe_scanner {
             let tiles_to_scan = get_los_tiles(&pos, &event.scanner_facing, &los, &board);
 
-            for pos in tiles_to_scan {
-                if let Some(occ) = board.occ_at(&pos) {
-                    if *occ != OccupantType::Empty {
-                        losreport_events.send(LOSReportEvent {
-                            scanned_type: *occ,
-                            scanner_id: event.scanner_id,
-                        });
-                        break;
-                    }
-                }
+            for (distance, scanned_type, scanned_pos) in
+                scan_los(&pos, &tiles_to_scan, &board, &config.scan)
+            {
+                losreport_events.send(LOSReportEvent {
+                    scanned_type,
+                    scanner_id: event.scanner_id,
+                    scanned_pos,
+                    distance,
+                    turn: turn.num,
+                });
             }
+            losscanned_events.send(LOSScannedEvent {
+                tiles: tiles_to_scan,
+            });
         }
 
         if let Ok((_, mut last_action, _)) = player_query.get_mut(event.scanner_id) {
@@ -398,75 +605,79 @@ fn player_scan_los_listener(
     }
 }
 
-fn player_los_report_listener(
+/// Whatever a scan found is remembered, so the scanner's brain can take it into account from the next turn on.
+/// The reports of a scan come closest first, and the first one of a newer scan replaces what the previous scan found.
+fn player_perception_listener(
     mut los_report_events: EventReader<LOSReportEvent>,
-    mut restore_colors_event: EventWriter<RestoreColorsEvent>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut color_query: Query<&mut Handle<ColorMaterial>>,
+    mut player_query: Query<&mut Perception, With<Player>>,
 ) {
     for event in los_report_events.read() {
-        if let Ok(mut scanner_color) = color_query.get_mut(event.scanner_id) {
-            let current_color = materials.get(scanner_color.as_ref()).unwrap().color;
-            if current_color != DEFAULT_COLOR_ON_LOS_DETECT {
-                restore_colors_event.send(RestoreColorsEvent {
-                    entity_id: event.scanner_id,
-                    old_color: current_color,
-                });
-                *scanner_color = materials.add(DEFAULT_COLOR_ON_LOS_DETECT);
-            }
-        }
-        let scanned_id = match event.scanned_type {
-            OccupantType::Player(player_id) => player_id,
-            OccupantType::Food(food_id) => food_id,
-            OccupantType::Wall(wall_id) => wall_id,
-            err_occ => unreachable!(
-                "Changing color on LOS: this entity type should not be scanned: {:?}",
-                err_occ
-            ),
-        };
-        if let Ok(mut scanned_color) = color_query.get_mut(scanned_id) {
-            let current_color = materials.get(scanned_color.as_ref()).unwrap().color;
-            if current_color != DEFAULT_COLOR_ON_LOS_DETECT {
-                restore_colors_event.send(RestoreColorsEvent {
-                    entity_id: scanned_id,
-                    old_color: current_color,
-                });
-                *scanned_color = materials.add(DEFAULT_COLOR_ON_LOS_DETECT);
+        if let Ok(mut perception) = player_query.get_mut(event.scanner_id) {
+            let sighting = Sighting {
+                seen: event.scanned_type,
+                distance: event.distance,
+                pos: event.scanned_pos,
+                turn: event.turn,
+            };
+            if perception
+                .last_seen
+                .is_none_or(|last| last.turn != event.turn)
+            {
+                perception.last_seen = Some(sighting);
+                perception.in_sight.clear();
             }
+            perception.in_sight.push(sighting);
         }
     }
 }
 
-fn restore_colors_listener(
-    mut restore_colors_events: EventReader<RestoreColorsEvent>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut color_query: Query<&mut Handle<ColorMaterial>>,
-) {
-    for event in restore_colors_events.read() {
-        if let Ok(mut color) = color_query.get_mut(event.entity_id) {
-            *color = materials.add(event.old_color);
-        }
-    }
-}
-
-fn advance_players(
+pub fn advance_players(
     mut kill_event: EventWriter<KillEvent>,
     mut eat_event: EventWriter<EatEvent>,
     mut move_event: EventWriter<MoveEvent>,
     mut turn_event: EventWriter<TurnEvent>,
     mut los_event: EventWriter<ScanLOSEvent>,
     mut build_wall_event: EventWriter<BuildWallEvent>,
+    mut deposit_pheromone_event: EventWriter<DepositPheromoneEvent>,
     mut update_vitals_event: EventWriter<UpdateVitalsEvent>,
+    board: Res<Board>,
+    scents: Res<ScentField>,
+    config: Res<SimConfig>,
+    turn: Res<Turn>,
+    mut rng: ResMut<SimRng>,
     mut player_query: Query<
-        (Entity, &BoardPosition, &mut FacingDirection, &Vitals),
+        (
+            Entity,
+            &BoardPosition,
+            &FacingDirection,
+            &LineOfSight,
+            &Vitals,
+            &Perception,
+            &mut Genome,
+            &mut PlayerActionType,
+        ),
         (With<Player>, Without<BoardTile>),
     >,
 ) {
-    for (player_id, player_pos, direction, vitals) in player_query.iter_mut() {
+    for (player_id, player_pos, direction, los, vitals, perception, mut genome, mut last_action) in
+        player_query.iter_mut()
+    {
         if vitals.status == PlayerStatus::DedPepega {
             continue;
         }
-        match random_player_action() {
+        // whatever the player decides, it's idle until its action actually happens
+        *last_action = PlayerActionType::Idle;
+        let situation = Situation::observe(
+            player_pos,
+            direction,
+            los,
+            vitals,
+            Memory::recall(perception, player_pos, direction, turn.num),
+            &board,
+            &scents,
+            &config,
+        );
+        match genome.decide(&situation, rng.as_mut()) {
             PlayerActionType::Idle => (),
             PlayerActionType::MoveForward => {
                 move_event.send(MoveEvent {
@@ -521,6 +732,11 @@ fn advance_players(
                     builder_id: player_id,
                 });
             }
+            PlayerActionType::DepositPheromone => {
+                deposit_pheromone_event.send(DepositPheromoneEvent {
+                    depositor_id: player_id,
+                });
+            }
             act => unreachable!(
                 "Incorrect action type while trying to advance players: {:#?}",
                 act
@@ -541,24 +757,30 @@ impl Plugin for PlayerActionPlugin {
             .add_event::<MoveEvent>()
             .add_event::<TurnEvent>()
             .add_event::<BuildWallEvent>()
+            .add_event::<DepositPheromoneEvent>()
             .add_event::<ScanLOSEvent>()
             .add_event::<LOSReportEvent>()
+            .add_event::<LOSScannedEvent>()
             .add_event::<UpdateVitalsEvent>()
-            .add_event::<RestoreColorsEvent>()
+            .init_resource::<TurnResolution>()
             .add_systems(
-                FixedUpdate,
-                (advance_players).run_if(in_state(VisualizerState::SimulationRunning)),
+                Update,
+                (advance_players)
+                    .run_if(in_state(VisualizerState::SimulationRunning))
+                    .run_if(turn_due),
             )
             .add_systems(
                 Update,
                 (
+                    resolve_player_intents,
+                    player_scan_los_listener,
+                    player_perception_listener,
                     player_turn_listener,
-                    player_move_listener,
-                    player_eat_listener,
                     player_kill_listener,
+                    player_eat_listener,
+                    player_move_listener,
                     player_build_wall_listener,
-                    player_scan_los_listener,
-                    player_los_report_listener,
+                    player_deposit_pheromone_listener,
                 )
                     .chain()
                     .run_if(in_state(VisualizerState::SimulationRunning))
@@ -567,10 +789,6 @@ impl Plugin for PlayerActionPlugin {
             .add_systems(
                 PostUpdate,
                 update_vitals_listener.run_if(in_state(VisualizerState::SimulationRunning)),
-            )
-            .add_systems(
-                FixedPostUpdate,
-                restore_colors_listener.after(player_los_report_listener),
             );
     }
 }
@@ -1,14 +1,19 @@
 use bevy::prelude::*;
-use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
 
-use std::f32::consts::PI;
-
-use crate::{engine::common::*, engine::config::*, engine::random::*, simulation::players::*};
-
-#[derive(Bundle)]
-pub struct BoardTileBundle {
-    pub sprite: SpriteBundle,
-}
+use crate::{
+    engine::actions::advance_players,
+    engine::common::*,
+    engine::config::*,
+    engine::islands::Island,
+    engine::lineage::LineageStore,
+    engine::options::RunOptions,
+    engine::random::*,
+    engine::report::{record_generation_stats, GenerationReport},
+    simulation::fsm::FsmBrain,
+    simulation::genome::*,
+    simulation::neat::SpeciesRegistry,
+    simulation::players::*,
+};
 
 #[derive(Bundle)]
 pub struct PlayerBundle {
@@ -17,12 +22,14 @@ pub struct PlayerBundle {
     pub los: LineOfSight,
     pub last_action_taken: PlayerActionType,
     pub vitals: Vitals,
-    pub sprite: MaterialMesh2dBundle<ColorMaterial>,
+    pub genome: Genome,
+    pub stats: PlayerStats,
+    pub perception: Perception,
 }
 
 #[derive(Resource)]
-struct Turn {
-    num: u32,
+pub struct Turn {
+    pub num: u32,
 }
 
 impl Turn {
@@ -31,81 +38,134 @@ impl Turn {
     }
 }
 
-fn spawn_board(
-    mut commands: Commands,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut board: ResMut<Board>,
-) {
-    let tile_color = Color::srgb(0.5, 0.5, 0.5);
-    materials.add(tile_color);
-
-    for x in 0..DEFAULT_GRID_SIZE {
-        for y in 0..DEFAULT_GRID_SIZE {
-            commands.spawn((
-                BoardTileBundle {
-                    sprite: SpriteBundle {
-                        sprite: Sprite {
-                            color: tile_color,
-                            custom_size: Some(Vec2::new(DEFAULT_TILE_SIZE, DEFAULT_TILE_SIZE)),
-                            ..default()
-                        },
-                        transform: Transform::from_xyz(grid_to_world(x), grid_to_world(y), 0.0),
-                        ..Default::default()
-                    },
-                },
-                BoardTile,
-            ));
-            board.add_occ(BoardPosition::new(x, y), OccupantType::Empty);
+#[derive(Resource)]
+pub struct Generation {
+    pub num: u32,
+}
+
+impl Generation {
+    pub fn new() -> Self {
+        Self { num: 1 }
+    }
+}
+
+/// Decides when the next turn is due: on a timer while someone is watching the simulation,
+/// or on every single frame when running headless.
+#[derive(Resource)]
+pub struct TurnPacing {
+    timer: Option<Timer>,
+}
+
+impl TurnPacing {
+    pub fn every(seconds: f64) -> Self {
+        Self {
+            timer: Some(Timer::from_seconds(seconds as f32, TimerMode::Repeating)),
         }
     }
+
+    pub fn every_frame() -> Self {
+        Self { timer: None }
+    }
+
+    pub fn turn_due(&self) -> bool {
+        self.timer
+            .as_ref()
+            .is_none_or(|timer| timer.just_finished())
+    }
+}
+
+impl FromWorld for TurnPacing {
+    fn from_world(world: &mut World) -> Self {
+        Self::every(world.resource::<SimConfig>().seconds_per_turn)
+    }
+}
+
+fn tick_turn_pacing(time: Res<Time>, mut pacing: ResMut<TurnPacing>) {
+    if let Some(timer) = pacing.timer.as_mut() {
+        timer.tick(time.delta());
+    }
+}
+
+pub fn turn_due(pacing: Res<TurnPacing>) -> bool {
+    pacing.turn_due()
+}
+
+/// The mutation rate offspring are bred with. It's the configured one,
+/// unless adaptive mutation moves it with the diversity of the population.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct MutationRate {
+    pub value: f32,
 }
 
+impl FromWorld for MutationRate {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            value: world.resource::<SimConfig>().mutation_rate,
+        }
+    }
+}
+
+/// Genomes bred at the end of a generation, waiting to be given a body at the start of the next one.
+#[derive(Resource, Default)]
+pub struct GenePool {
+    pub genomes: Vec<Genome>,
+}
+
+/// Players get their genome ids as they're spawned, so that even the ones that die on the very first turn
+/// end up in the family tree.
 fn spawn_players(
     mut commands: Commands,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
     mut board: ResMut<Board>,
+    mut gene_pool: ResMut<GenePool>,
+    mut lineage: ResMut<LineageStore>,
+    generation: Res<Generation>,
+    mut rng: ResMut<SimRng>,
+    config: Res<SimConfig>,
 ) {
-    for _ in 0..default_player_count() {
+    let mut genome_rng = rng.fork();
+    for _ in 0..config.player_count() {
+        let mut genome = gene_pool
+            .genomes
+            .pop()
+            .unwrap_or_else(|| Genome::random(config.brain, &mut genome_rng));
+        // unless shapes evolve, everybody sees the configured shape, whatever shape their genome came with
+        genome.los_shape = match config.evolve_los_shape {
+            true => Some(genome.los_shape.unwrap_or(config.los_shape)),
+            false => None,
+        };
+        let los = config.line_of_sight(genome.los_shape.unwrap_or(config.los_shape));
+        genome.strength = match config.combat.damage_scaling {
+            DamageScaling::Strength => Some(genome.strength.unwrap_or(1.0)),
+            DamageScaling::Energy => None,
+        };
+        let health = config.combat.health(genome.strength.unwrap_or(1.0));
         loop {
-            let random_pos = BoardPosition::from_tuple(random_board_pos());
+            let random_pos = BoardPosition::from_tuple(random_board_pos(
+                board.width(),
+                board.height(),
+                rng.as_mut(),
+            ));
 
             if let Some(occupant) = board.occ_at_mut(&random_pos) {
                 if *occupant != OccupantType::Empty {
                     continue;
                 }
-                let triangle = Mesh2dHandle(meshes.add(Triangle2d::new(
-                    Vec2::new(0., default_entity_size() / 2.),
-                    Vec2::new(-default_entity_size() / 2., -default_entity_size() / 2.),
-                    Vec2::new(default_entity_size() / 2., -default_entity_size() / 2.),
-                )));
-                *occupant = OccupantType::Player(
-                    commands
-                        .spawn((
-                            PlayerBundle {
-                                board_pos: random_pos,
-                                is_facing: FacingDirection::Right,
-                                los: LineOfSight {
-                                    length: DEFAULT_LOS_LENGTH,
-                                },
-                                last_action_taken: PlayerActionType::Idle,
-                                vitals: Vitals::new(random_energy_start()),
-                                sprite: MaterialMesh2dBundle {
-                                    mesh: triangle,
-                                    material: materials.add(DEFAULT_PLAYER_COLOR),
-                                    transform: Transform::from_xyz(
-                                        grid_to_world(random_pos.x),
-                                        grid_to_world(random_pos.y),
-                                        1.0,
-                                    )
-                                    .with_rotation(Quat::from_rotation_z(-PI / 2.0)),
-                                    ..default()
-                                },
-                            },
-                            Player,
-                        ))
-                        .id(),
-                );
+                let player = commands.spawn_empty().id();
+                lineage.record_birth(player, &mut genome, generation.num, rng.seed());
+                *occupant = OccupantType::Player(player);
+                commands.entity(player).insert((
+                    PlayerBundle {
+                        board_pos: random_pos,
+                        is_facing: FacingDirection::Right,
+                        los,
+                        last_action_taken: PlayerActionType::Idle,
+                        vitals: Vitals::new(random_energy_start(&config, rng.as_mut()), health),
+                        genome,
+                        stats: PlayerStats::default(),
+                        perception: Perception::default(),
+                    },
+                    Player,
+                ));
                 break;
             }
         }
@@ -114,13 +174,17 @@ fn spawn_players(
 
 fn spawn_food(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
     mut board: ResMut<Board>,
+    mut rng: ResMut<SimRng>,
+    config: Res<SimConfig>,
 ) {
-    for _ in 0..default_food_count() {
+    for _ in 0..config.food_count() {
         loop {
-            let random_pos = BoardPosition::from_tuple(random_board_pos());
+            let random_pos = BoardPosition::from_tuple(random_board_pos(
+                board.width(),
+                board.height(),
+                rng.as_mut(),
+            ));
             if let Some(occ) = board.occ_at(&random_pos) {
                 if *occ != OccupantType::Empty {
                     continue;
@@ -130,9 +194,8 @@ fn spawn_food(
                     &mut commands,
                     random_pos,
                     FoodType::Meal,
+                    &config,
                     &mut board,
-                    &mut materials,
-                    &mut meshes,
                 )
                 .unwrap();
                 break;
@@ -141,49 +204,251 @@ fn spawn_food(
     }
 }
 
-fn advance_turn(mut turn: ResMut<Turn>, mut states: ResMut<NextState<VisualizerState>>) {
+/// Count the turn that's about to be played. Runs right before the players act and under the same conditions,
+/// so every turn that's counted gets played, and everything that happens during a turn knows the turn's number.
+pub fn advance_turn(mut turn: ResMut<Turn>) {
     turn.num += 1;
+}
 
-    if turn.num > TURNS_PER_GEN {
+/// End the generation once its last turn has been played.
+/// The state only changes on the next frame, before anybody gets to act again.
+fn finish_generation(
+    turn: Res<Turn>,
+    mut states: ResMut<NextState<VisualizerState>>,
+    config: Res<SimConfig>,
+) {
+    if turn.num >= config.turns_per_gen {
         states.set(VisualizerState::GenerationFinished);
     }
 }
 
-fn log_survival_rate(player_query: Query<&Vitals, With<Player>>) {
-    let mut survived: u32 = 0;
-    let mut not_killed: u32 = 0;
-    for vitals in player_query.iter() {
-        match vitals.status {
-            PlayerStatus::Alive => survived += 1,
-            PlayerStatus::DedPepega => not_killed += 1,
-        }
-    }
+fn log_survival_rate(
+    generation: Res<Generation>,
+    config: Res<SimConfig>,
+    report: Res<GenerationReport>,
+    player_query: Query<&Vitals, With<Player>>,
+    island: Option<Res<Island>>,
+) {
+    let survived = player_query
+        .iter()
+        .filter(|vitals| vitals.status == PlayerStatus::Alive)
+        .count() as u32;
+    let island = island.map_or(String::new(), |island| {
+        format!("[island {}] ", island.index + 1)
+    });
     warn!(
-        "Simulation over! Started with {} players. Survived: {} players, murdered: {} players, died from hunger: {} players. Survival rate: {:.2}%.",
-        default_player_count(),
+        "{}Generation {} over! Started with {} players. Survived: {} players, murdered: {} players, died from hunger: {} players, died otherwise: {} players. Survival rate: {:.2}%.",
+        island,
+        generation.num,
+        config.player_count(),
         survived,
-        default_player_count() - survived - not_killed,
-        not_killed,
-        (survived as f32 / default_player_count() as f32) * 100.
+        report.killed(),
+        report.starved(),
+        report.died_otherwise(),
+        (survived as f32 / config.player_count() as f32) * 100.
+    );
+    let accounted_for = survived + report.deaths.len() as u32;
+    if accounted_for != config.player_count() {
+        warn!(
+            "{}Generation {}: {} players are unaccounted for!",
+            island,
+            generation.num,
+            config.player_count() as i64 - accounted_for as i64
+        );
+    }
+}
+
+/// Every player of the generation, dead or alive, along with its fitness, fittest first.
+pub fn rank_players<'a>(
+    player_query: &'a Query<(&Vitals, &PlayerStats, &Genome), With<Player>>,
+    report: &'a GenerationReport,
+    config: &SimConfig,
+) -> Vec<(f32, &'a Genome)> {
+    let fitness = config.fitness.function();
+    let mut ranked: Vec<(f32, &Genome)> = player_query
+        .iter()
+        .filter(|(vitals, _, _)| vitals.status == PlayerStatus::Alive)
+        .map(|(_, stats, genome)| (fitness.score(stats), genome))
+        .chain(
+            report
+                .deaths
+                .iter()
+                .map(|death| (fitness.score(&death.stats), &death.genome)),
+        )
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    ranked
+}
+
+/// Mutate more when the generation that was just played had too little genetic diversity, and less when it had plenty.
+fn adapt_mutation_rate(
+    mut mutation_rate: ResMut<MutationRate>,
+    report: Res<GenerationReport>,
+    config: Res<SimConfig>,
+) {
+    let adaptive = &config.adaptive_mutation;
+    if !adaptive.enabled {
+        return;
+    }
+    let adapted = if report.mean_genome_distance < adaptive.low_diversity {
+        mutation_rate.value * adaptive.factor
+    } else if report.mean_genome_distance > adaptive.high_diversity {
+        mutation_rate.value / adaptive.factor
+    } else {
+        return;
+    };
+    mutation_rate.value = adapted.clamp(adaptive.min_rate, adaptive.max_rate);
+    debug!(
+        "Mean genome distance {:.4}, mutation rate is now {:.4}.",
+        report.mean_genome_distance, mutation_rate.value
     );
 }
 
+/// Breed a full population of offspring from the players of the generation, dead or alive, ranked by
+/// the configured fitness function, with parents picked by the configured selection strategy.
+/// The elites go into the next generation as they are, and NEAT genomes are bred species by species.
+pub fn breed_next_generation(
+    player_query: Query<(&Vitals, &PlayerStats, &Genome), With<Player>>,
+    report: Res<GenerationReport>,
+    mut gene_pool: ResMut<GenePool>,
+    mut species: ResMut<SpeciesRegistry>,
+    mut rng: ResMut<SimRng>,
+    mutation_rate: Res<MutationRate>,
+    config: Res<SimConfig>,
+) {
+    let candidates = rank_players(&player_query, &report, &config);
+
+    gene_pool.genomes.clear();
+    if candidates.is_empty() {
+        warn!("There's nobody to breed from, the next generation starts from scratch.");
+        return;
+    }
+    if let Some(fittest) = candidates[0].1.brain.as_any().downcast_ref::<FsmBrain>() {
+        debug!("The fittest state machine of the generation:\n{}", fittest);
+    }
+
+    let selection = config.selection.strategy(&config);
+    gene_pool.genomes.extend(
+        candidates
+            .iter()
+            .take(config.elites as usize)
+            .map(|(_, genome)| (*genome).clone()),
+    );
+    let offspring_count = config.player_count() as usize - gene_pool.genomes.len();
+
+    if config.brain == BrainKind::Neat {
+        let offspring = species.breed(
+            &candidates,
+            offspring_count,
+            selection.as_ref(),
+            mutation_rate.value,
+            rng.as_mut(),
+        );
+        gene_pool.genomes.extend(offspring);
+        return;
+    }
+
+    let scores: Vec<f32> = candidates.iter().map(|(score, _)| *score).collect();
+    for _ in 0..offspring_count {
+        let (_, mother) = candidates[selection.select(&scores, rng.as_mut())];
+        let (_, father) = candidates[selection.select(&scores, rng.as_mut())];
+        let mut child = mother.crossover(father, rng.as_mut());
+        child.mutate(mutation_rate.value, rng.as_mut());
+        gene_pool.genomes.push(child);
+    }
+}
+
+/// Remove every player, piece of food and wall from the board.
+pub fn clear_board(
+    mut commands: Commands,
+    occupant_query: Query<Entity, With<BoardPosition>>,
+    mut board: ResMut<Board>,
+) {
+    for occupant in occupant_query.iter() {
+        commands.entity(occupant).despawn_recursive();
+    }
+    board.clear();
+}
+
+/// Move on to the next generation, or stop the app if the requested number of generations has been simulated.
+/// The next generation is already on the board and counted when the app stops, so it can be picked up by whoever ran it.
+/// Runs once the board is cleared, so that the players spawned next are born into the new generation.
+fn advance_generation(mut turn: ResMut<Turn>, mut generation: ResMut<Generation>) {
+    turn.num = 0;
+    generation.num += 1;
+}
+
+fn start_next_generation(
+    generation: Res<Generation>,
+    mut states: ResMut<NextState<VisualizerState>>,
+    options: Res<RunOptions>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if options
+        .generations
+        .is_some_and(|limit| generation.num > limit)
+    {
+        exit.send(AppExit::Success);
+        return;
+    }
+
+    states.set(VisualizerState::SimulationRunning);
+}
+
+fn log_seed(rng: Res<SimRng>, island: Option<Res<Island>>) {
+    match island {
+        Some(island) => info!(
+            "Random seed for this run: {} (island {} of {})",
+            island.run_seed,
+            island.index + 1,
+            island.count
+        ),
+        None => info!("Random seed for this run: {}", rng.seed()),
+    }
+}
+
+/// Needs a `SimConfig` resource to already be there when it's added to the app.
 pub struct GameBoardPlugin;
 
 impl Plugin for GameBoardPlugin {
     fn build(&self, app: &mut App) {
         app.init_state::<VisualizerState>()
-            .insert_resource(Board::new())
-            .insert_resource(Time::<Fixed>::from_seconds(SECONDS_PER_TURN))
+            .init_resource::<Board>()
+            .init_resource::<TurnPacing>()
             .insert_resource(Turn::new())
-            .add_systems(Startup, (spawn_board, spawn_players, spawn_food).chain())
+            .insert_resource(Generation::new())
+            .init_resource::<GenePool>()
+            .init_resource::<SpeciesRegistry>()
+            .init_resource::<MutationRate>()
+            .add_systems(Startup, (log_seed, spawn_players, spawn_food).chain())
+            .add_systems(PreUpdate, tick_turn_pacing)
             .add_systems(
-                FixedUpdate,
-                (advance_turn).run_if(in_state(VisualizerState::SimulationRunning)),
+                Update,
+                advance_turn
+                    .before(advance_players)
+                    .run_if(in_state(VisualizerState::SimulationRunning))
+                    .run_if(turn_due),
+            )
+            .add_systems(
+                PostUpdate,
+                finish_generation
+                    .run_if(in_state(VisualizerState::SimulationRunning))
+                    .run_if(turn_due),
             )
             .add_systems(
                 OnEnter(VisualizerState::GenerationFinished),
-                log_survival_rate,
+                (
+                    log_survival_rate,
+                    record_generation_stats,
+                    adapt_mutation_rate,
+                    breed_next_generation,
+                    clear_board,
+                    advance_generation,
+                    spawn_players,
+                    spawn_food,
+                    start_next_generation,
+                )
+                    .chain(),
             );
     }
 }
@@ -1,10 +1,10 @@
-use std::{collections::HashMap, error::Error, fmt::Display};
+use std::hash::Hasher;
+use std::{error::Error, fmt::Display};
 
 use bevy::prelude::*;
-use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
 
 use crate::engine::config::*;
-use crate::simulation::players::{Energy, FacingDirection, Food, LineOfSight};
+use crate::simulation::players::{Energy, FacingDirection, Food, LineOfSight, ScanMode};
 
 #[derive(Debug)]
 pub struct RungerError {
@@ -25,6 +25,76 @@ impl Display for RungerError {
 
 impl Error for RungerError {}
 
+/// 64-bit FNV-1a, with every integer fed to it as little-endian bytes and sizes as 64-bit integers.
+/// Unlike the standard library's `DefaultHasher`, it hashes the same across runs and platforms with the same build,
+/// so it's what innovation numbers, ids and fingerprints that end up in files are made with. Values hashed through
+/// `#[derive(Hash)]` may still hash differently after a Rust upgrade, as std doesn't promise that byte stream stays the same.
+#[derive(Debug, Clone, Copy)]
+pub struct StableHasher(u64);
+
+impl StableHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
 #[derive(Component, Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub struct BoardPosition {
     pub x: u32,
@@ -39,6 +109,11 @@ impl BoardPosition {
     pub fn from_tuple((x, y): (u32, u32)) -> Self {
         Self { x, y }
     }
+
+    /// How many steps it takes to get from one position to the other, diagonal steps included.
+    pub fn distance_to(&self, other: &BoardPosition) -> u32 {
+        self.x.abs_diff(other.x).max(self.y.abs_diff(other.y))
+    }
 }
 
 #[derive(Component, Debug, PartialEq, Eq, Hash, Copy, Clone)]
@@ -49,9 +124,21 @@ pub enum OccupantType {
     Wall(Entity),
 }
 
+/// The board is a dense grid of tiles, stored row by row:
+/// the tile at `(x, y)` lives at index `y * width + x`.
 #[derive(Resource)]
 pub struct Board {
-    occupants: HashMap<BoardPosition, OccupantType>,
+    width: u32,
+    height: u32,
+    disengage_length: u32,
+    occupants: Vec<OccupantType>,
+}
+
+impl FromWorld for Board {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.resource::<SimConfig>();
+        Self::new(config.grid_size, config.grid_size, config.disengage_length)
+    }
 }
 
 impl Board {
@@ -76,9 +163,9 @@ impl Board {
             FacingDirection::Down => FacingDirection::Up,
         };
         let mut cur_pos = *pos;
-        for _ in 1..=DISENGAGE_LENGTH {
+        for _ in 1..=self.disengage_length {
             let test_pos = Self::looking_pos(&cur_pos, &disengage_direction);
-            if Self::pos_within_bounds(&test_pos) {
+            if self.pos_within_bounds(&test_pos) {
                 let new_pos = BoardPosition::new(test_pos.0 as u32, test_pos.1 as u32);
                 if let Some(occ) = self.occ_at(&new_pos) {
                     if *occ == OccupantType::Empty {
@@ -97,25 +184,51 @@ impl Board {
         }
     }
 
-    fn pos_within_bounds(pos_to_check: &(i32, i32)) -> bool {
+    fn pos_within_bounds(&self, pos_to_check: &(i32, i32)) -> bool {
         pos_to_check.0 >= 0
             && pos_to_check.1 >= 0
-            && pos_to_check.0 < DEFAULT_GRID_SIZE as i32
-            && pos_to_check.1 < DEFAULT_GRID_SIZE as i32
+            && pos_to_check.0 < self.width as i32
+            && pos_to_check.1 < self.height as i32
+    }
+
+    fn index(&self, pos: &BoardPosition) -> Option<usize> {
+        if pos.x < self.width && pos.y < self.height {
+            Some((pos.y * self.width + pos.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn pos_at(&self, index: usize) -> BoardPosition {
+        BoardPosition::new(index as u32 % self.width, index as u32 / self.width)
     }
 
-    pub fn new() -> Self {
+    /// A board of the given dimensions with every tile empty.
+    /// `disengage_length` is how many tiles a player can retreat in one move.
+    pub fn new(width: u32, height: u32, disengage_length: u32) -> Self {
         Self {
-            occupants: HashMap::new(),
+            width,
+            height,
+            disengage_length,
+            occupants: vec![OccupantType::Empty; (width * height) as usize],
         }
     }
 
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
     pub fn occ_at(&self, pos: &BoardPosition) -> Option<&OccupantType> {
-        self.occupants.get(pos)
+        self.index(pos).map(|i| &self.occupants[i])
     }
 
     pub fn occ_at_mut(&mut self, pos: &BoardPosition) -> Option<&mut OccupantType> {
-        self.occupants.get_mut(pos)
+        let index = self.index(pos)?;
+        self.occupants.get_mut(index)
     }
 
     pub fn looking_at(
@@ -124,23 +237,9 @@ impl Board {
         looker_facing: &FacingDirection,
     ) -> Option<(BoardPosition, &OccupantType)> {
         let looking_pos = Board::looking_pos(looker_pos, looker_facing);
-        if Self::pos_within_bounds(&looking_pos) {
-            let pos = BoardPosition::new(looking_pos.0 as u32, looking_pos.1 as u32);
-            self.occupants.get(&pos).map(|o| (pos, o))
-        } else {
-            None
-        }
-    }
-
-    pub fn looking_at_mut(
-        &mut self,
-        looker_pos: &BoardPosition,
-        looker_facing: &FacingDirection,
-    ) -> Option<(BoardPosition, &mut OccupantType)> {
-        let looking_pos = Board::looking_pos(looker_pos, looker_facing);
-        if Self::pos_within_bounds(&looking_pos) {
+        if self.pos_within_bounds(&looking_pos) {
             let pos = BoardPosition::new(looking_pos.0 as u32, looking_pos.1 as u32);
-            self.occupants.get_mut(&pos).map(|o| (pos, o))
+            self.occ_at(&pos).map(|o| (pos, o))
         } else {
             None
         }
@@ -152,33 +251,97 @@ impl Board {
         coward_facing: &FacingDirection,
     ) -> Option<(BoardPosition, &OccupantType)> {
         if let Some(dpos) = self.max_disengage_position(coward_pos, coward_facing) {
-            self.occupants.get(&dpos).map(|o| (dpos, o))
+            self.occ_at(&dpos).map(|o| (dpos, o))
         } else {
             None
         }
     }
 
-    pub fn disengage_to_mut(
-        &mut self,
-        coward_pos: &BoardPosition,
-        coward_facing: &FacingDirection,
-    ) -> Option<(BoardPosition, &mut OccupantType)> {
-        if let Some(dpos) = self.max_disengage_position(coward_pos, coward_facing) {
-            self.occupants.get_mut(&dpos).map(|o| (dpos, o))
-        } else {
-            None
+    /// Whether any tile on the straight line between two positions holds a wall.
+    pub fn wall_between(&self, from: &BoardPosition, to: &BoardPosition) -> bool {
+        Self::line_between(from, to)
+            .iter()
+            .any(|pos| matches!(self.occ_at(pos), Some(OccupantType::Wall(_))))
+    }
+
+    /// The tiles on the straight line between two positions, both of them excluded, traced with Bresenham's algorithm.
+    pub fn line_between(from: &BoardPosition, to: &BoardPosition) -> Vec<BoardPosition> {
+        let mut line = vec![];
+        let (mut x, mut y) = (from.x as i32, from.y as i32);
+        let (to_x, to_y) = (to.x as i32, to.y as i32);
+        let (dx, dy) = ((to_x - x).abs(), -(to_y - y).abs());
+        let (step_x, step_y) = ((to_x - x).signum(), (to_y - y).signum());
+        let mut error = dx + dy;
+        loop {
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+            if (x, y) == (to_x, to_y) {
+                return line;
+            }
+            line.push(BoardPosition::new(x as u32, y as u32));
         }
     }
 
-    pub fn add_occ(&mut self, pos: BoardPosition, occ: OccupantType) {
-        self.occupants.insert(pos, occ);
+    /// Mark every tile of the board as empty, keeping the board's dimensions.
+    pub fn clear(&mut self) {
+        self.occupants.fill(OccupantType::Empty);
+    }
+
+    /// Every tile of the board along with its position, row by row.
+    pub fn tiles(&self) -> impl Iterator<Item = (BoardPosition, &OccupantType)> {
+        self.occupants
+            .iter()
+            .enumerate()
+            .map(move |(i, occ)| (self.pos_at(i), occ))
+    }
+
+    /// Every tile of the rectangle between two corners (both inclusive), clipped to the board.
+    pub fn rect(
+        &self,
+        from: &BoardPosition,
+        to: &BoardPosition,
+    ) -> impl Iterator<Item = (BoardPosition, &OccupantType)> {
+        let x_range = from.x.min(to.x)..=from.x.max(to.x).min(self.width.saturating_sub(1));
+        let y_range = from.y.min(to.y)..=from.y.max(to.y).min(self.height.saturating_sub(1));
+        y_range.flat_map(move |y| {
+            x_range.clone().filter_map(move |x| {
+                let pos = BoardPosition::new(x, y);
+                self.occ_at(&pos).map(|occ| (pos, occ))
+            })
+        })
+    }
+
+    /// Every tile within `radius` tiles of `center` in any direction (diagonals included),
+    /// except the center itself.
+    pub fn neighbourhood(
+        &self,
+        center: &BoardPosition,
+        radius: u32,
+    ) -> impl Iterator<Item = (BoardPosition, &OccupantType)> {
+        let center = *center;
+        let from = BoardPosition::new(
+            center.x.saturating_sub(radius),
+            center.y.saturating_sub(radius),
+        );
+        let to = BoardPosition::new(
+            center.x.saturating_add(radius),
+            center.y.saturating_add(radius),
+        );
+        self.rect(&from, &to).filter(move |(pos, _)| *pos != center)
     }
 }
 
 #[derive(Component, Debug, Copy, Clone)]
 pub struct BoardTile;
 
-#[derive(Debug)]
+#[derive(Component, Debug, Clone, Copy)]
 pub enum FoodType {
     Meal,
     DeadMeat(u32),
@@ -188,13 +351,12 @@ pub enum FoodType {
 pub struct FoodBundle {
     pub board_pos: BoardPosition,
     pub energy_value: Energy,
-    pub sprite: MaterialMesh2dBundle<ColorMaterial>,
+    pub food_type: FoodType,
 }
 
 #[derive(Bundle)]
 pub struct WallBundle {
     pub board_pos: BoardPosition,
-    pub sprite: SpriteBundle,
 }
 
 #[derive(Default, Clone, Copy, States, Debug, Hash, PartialEq, Eq)]
@@ -204,9 +366,9 @@ pub enum VisualizerState {
     GenerationFinished,
 }
 
-pub fn grid_to_world(grid_pos: u32) -> f32 {
+pub fn grid_to_world(grid_pos: u32, grid_size: u32) -> f32 {
     grid_pos as f32 * (DEFAULT_TILE_SIZE + default_tile_margin())
-        - DEFAULT_GRID_SIZE as f32 * (DEFAULT_TILE_SIZE + default_tile_margin()) / 2.0
+        - grid_size as f32 * (DEFAULT_TILE_SIZE + default_tile_margin()) / 2.0
         + DEFAULT_TILE_SIZE / 2.0
 }
 
@@ -214,13 +376,12 @@ pub fn place_food_at(
     commands: &mut Commands,
     pos: BoardPosition,
     food_type: FoodType,
+    config: &SimConfig,
     board: &mut ResMut<Board>,
-    materials: &mut ResMut<Assets<ColorMaterial>>,
-    meshes: &mut ResMut<Assets<Mesh>>,
 ) -> Result<(), Box<dyn Error>> {
-    let (energy_value, food_color) = match food_type {
-        FoodType::Meal => (default_food_value(), DEFAULT_FOOD_COLOR),
-        FoodType::DeadMeat(energy_val) => (energy_val, DEAD_MEAT_COLOR),
+    let energy_value = match food_type {
+        FoodType::Meal => config.food_value(),
+        FoodType::DeadMeat(energy_val) => energy_val,
     };
 
     if let Some(occupant) = board.occ_at_mut(&pos) {
@@ -230,25 +391,13 @@ pub fn place_food_at(
                 &pos, occupant
             )));
         };
-        let mesh = meshes.add(Circle {
-            radius: default_entity_size() / 2.,
-        });
         *occupant = OccupantType::Food(
             commands
                 .spawn((
                     FoodBundle {
                         energy_value: Energy::new(energy_value),
                         board_pos: pos,
-                        sprite: MaterialMesh2dBundle {
-                            mesh: Mesh2dHandle(mesh),
-                            material: materials.add(food_color),
-                            transform: Transform::from_xyz(
-                                grid_to_world(pos.x),
-                                grid_to_world(pos.y),
-                                0.9,
-                            ),
-                            ..default()
-                        },
+                        food_type,
                     },
                     Food,
                 ))
@@ -263,24 +412,76 @@ pub fn place_food_at(
     )))
 }
 
+/// Every tile within the scanner's line of sight, nearest first by `BoardPosition::distance_to` (ties go to the tiles
+/// closer to a straight line from the scanner, then to the ones further ahead, then from left to right).
+/// Walls hide whatever is behind them: a tile is only seen if the straight line from the scanner to it
+/// doesn't cross a wall on the way, so walls themselves are seen, but nothing beyond them.
 pub fn get_los_tiles(
     scanner_pos: &BoardPosition,
     scanner_facing: &FacingDirection,
     scanner_los: &LineOfSight,
     board: &Board,
 ) -> Vec<BoardPosition> {
-    let mut los_tiles = vec![];
-
-    let mut pos_index = *scanner_pos;
-    for _ in 0..scanner_los.length {
-        match board.looking_at(&pos_index, scanner_facing) {
-            Some((pos, _)) => {
-                los_tiles.push(pos);
-                pos_index = pos;
-            }
-            None => break,
-        }
-    }
+    let mut los_tiles: Vec<(i32, i32, BoardPosition)> = board
+        .neighbourhood(scanner_pos, scanner_los.length)
+        .filter_map(|(pos, _)| {
+            let (dx, dy) = (
+                pos.x as i32 - scanner_pos.x as i32,
+                pos.y as i32 - scanner_pos.y as i32,
+            );
+            let (ahead, right) = match scanner_facing {
+                FacingDirection::Up => (dy, dx),
+                FacingDirection::Right => (dx, -dy),
+                FacingDirection::Down => (-dy, -dx),
+                FacingDirection::Left => (-dx, dy),
+            };
+            scanner_los
+                .covers(ahead, right)
+                .then_some((ahead, right, pos))
+        })
+        .collect();
+    los_tiles.sort_by_key(|(ahead, right, _)| {
+        (
+            ahead.abs().max(right.abs()),
+            ahead * ahead + right * right,
+            -ahead,
+            *right,
+        )
+    });
 
     los_tiles
+        .into_iter()
+        .map(|(_, _, pos)| pos)
+        .filter(|pos| !board.wall_between(scanner_pos, pos))
+        .collect()
+}
+
+/// Everything the scanner can make out on the tiles of its line of sight (as given by `get_los_tiles`),
+/// as `(distance, occupant, position)`, closest first: only the closest thing in the `first` scan mode,
+/// and in the `full` one everything that isn't hidden behind too much food and too many players (see `ScanSettings`).
+pub fn scan_los(
+    scanner_pos: &BoardPosition,
+    los_tiles: &[BoardPosition],
+    board: &Board,
+    settings: &ScanSettings,
+) -> Vec<(u32, OccupantType, BoardPosition)> {
+    let visible = los_tiles
+        .iter()
+        .copied()
+        .filter_map(|pos| match board.occ_at(&pos) {
+            Some(OccupantType::Empty) | None => None,
+            Some(occ) => Some((scanner_pos.distance_to(&pos), *occ, pos)),
+        })
+        .filter(|(_, _, pos)| {
+            let visibility: f32 = Board::line_between(scanner_pos, pos)
+                .iter()
+                .filter_map(|between| board.occ_at(between))
+                .map(|between| settings.transparency(between))
+                .product();
+            visibility >= settings.min_visibility
+        });
+    match settings.mode {
+        ScanMode::First => visible.take(1).collect(),
+        ScanMode::Full => visible.collect(),
+    }
 }
@@ -1,5 +1,15 @@
+use std::error::Error;
+use std::path::Path;
+
+use bevy::prelude::{Color, Resource};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::common::{rerror, OccupantType};
+use crate::engine::islands::MigrationTopology;
+use crate::simulation::fitness::FitnessWeights;
+use crate::simulation::genome::BrainKind;
 use crate::simulation::players::*;
-use bevy::prelude::Color;
+use crate::simulation::selection::SelectionKind;
 
 // UTILITIES
 
@@ -20,15 +30,16 @@ pub fn default_entity_size() -> f32 {
 pub fn default_tile_margin() -> f32 {
     DEFAULT_TILE_SIZE * percent(15)
 }
-pub fn default_player_count() -> u32 {
-    ((DEFAULT_GRID_SIZE * DEFAULT_GRID_SIZE) as f32 * percent(30)) as u32
-}
+pub const DEFAULT_PLAYER_PERCENT: u8 = 30;
 
 pub const DEFAULT_PLAYER_COLOR: Color = Color::srgb(1.0, 0.0, 0.0);
 pub const DEFAULT_FOOD_COLOR: Color = Color::srgb(1., 0.5, 0.);
 pub const DEAD_MEAT_COLOR: Color = Color::srgb(0., 0., 0.);
 pub const DEFAULT_WALL_COLOR: Color = Color::srgb(0.4, 0.26, 0.13);
 pub const DEFAULT_COLOR_ON_LOS_DETECT: Color = Color::srgb(0.8, 1.0, 1.0);
+pub const DEFAULT_TILE_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+/// What the tiles a scan covers turn into with `--show-los`.
+pub const DEFAULT_LOS_TILE_COLOR: Color = Color::srgb(0.6, 0.7, 0.8);
 
 // SIMULATION
 
@@ -36,31 +47,51 @@ pub const TURNS_PER_GEN: u32 = 300;
 pub const SECONDS_PER_TURN: f64 = 0.1;
 
 // FOOD
-pub fn default_food_count() -> u32 {
-    (default_player_count() as f32 * percent(50)) as u32
-}
-pub fn default_energy_min() -> u32 {
-    TURNS_PER_GEN / 2
-}
-pub fn default_energy_max() -> u32 {
-    (TURNS_PER_GEN as f32 / 1.5) as u32
-}
-pub fn default_food_value() -> u32 {
-    TURNS_PER_GEN / 3 * 2
-}
+
+/// How much food there is on the board, as a percentage of the player count.
+pub const DEFAULT_FOOD_PERCENT: u8 = 50;
+pub const DEFAULT_STARVED_CORPSE_VALUE: u32 = 50;
 
 // ACTIONS
 
-pub fn action_cost(action_type: &PlayerActionType) -> u32 {
-    match *action_type {
-        PlayerActionType::Idle => 1,
-        PlayerActionType::Turn(_) => 1,
-        PlayerActionType::ScanLOS => 1,
-        PlayerActionType::Eat => 2,
-        PlayerActionType::MoveForward => 3,
-        PlayerActionType::MoveBackwards => 3,
-        PlayerActionType::BuildWall => 20,
-        PlayerActionType::Kill => 40,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActionCosts {
+    pub idle: u32,
+    pub turn: u32,
+    /// Scanning with a straight line of sight.
+    pub scan_los: u32,
+    pub scan_los_cone: u32,
+    pub scan_los_circle: u32,
+    pub eat: u32,
+    pub move_forward: u32,
+    pub move_backwards: u32,
+    pub build_wall: u32,
+    pub kill: u32,
+    pub deposit_pheromone: u32,
+    /// Paid every turn on top of the action by players seeing a cone rather than a straight line,
+    /// as they see their whole line of sight whether they scan or not.
+    pub cone_sight: u32,
+    pub circle_sight: u32,
+}
+
+impl Default for ActionCosts {
+    fn default() -> Self {
+        Self {
+            idle: 1,
+            turn: 1,
+            scan_los: 1,
+            scan_los_cone: 2,
+            scan_los_circle: 3,
+            eat: 2,
+            move_forward: 3,
+            move_backwards: 3,
+            build_wall: 20,
+            kill: 40,
+            deposit_pheromone: 2,
+            cone_sight: 1,
+            circle_sight: 2,
+        }
     }
 }
 
@@ -69,3 +100,521 @@ pub const DISENGAGE_LENGTH: u32 = 3;
 // LINE OF SIGHT MECHANICS
 
 pub const DEFAULT_LOS_LENGTH: u32 = 3;
+pub const DEFAULT_LOS_SHAPE: LosShape = LosShape::Straight;
+pub const DEFAULT_LOS_CONE_HALF_ANGLE: u32 = 45;
+pub const DEFAULT_LOS_CIRCLE_RADIUS: u32 = 2;
+
+/// What a scan reports and what it can see through. Walls always hide whatever is behind them,
+/// food and players only partly: everything in the way of a tile leaves only its transparency's share
+/// of the view through, and the tile can't be made out anymore below `min_visibility`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanSettings {
+    pub mode: ScanMode,
+    pub food_transparency: f32,
+    pub player_transparency: f32,
+    pub min_visibility: f32,
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        Self {
+            mode: ScanMode::First,
+            food_transparency: 0.5,
+            player_transparency: 0.25,
+            min_visibility: 0.2,
+        }
+    }
+}
+
+impl ScanSettings {
+    /// How much of the view the occupant of a tile lets through.
+    pub fn transparency(&self, occupant: &OccupantType) -> f32 {
+        match occupant {
+            OccupantType::Empty => 1.0,
+            OccupantType::Food(_) => self.food_transparency,
+            OccupantType::Player(_) => self.player_transparency,
+            OccupantType::Wall(_) => 0.0,
+        }
+    }
+}
+
+// COMBAT
+
+/// What a player's attacks get stronger with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DamageScaling {
+    /// The attacker's energy: well fed players hit harder.
+    Energy,
+    /// The attacker's strength, a trait it inherits from its parents. Strong players have less health, though.
+    Strength,
+}
+
+pub const MIN_STRENGTH: f32 = 0.5;
+pub const MAX_STRENGTH: f32 = 2.0;
+/// The standard deviation of the noise added to a mutated strength.
+pub const STRENGTH_MUTATION_STRENGTH: f32 = 0.1;
+
+/// How players hurt each other. Every attack takes some of the victim's health, and players die once
+/// they have none left. A victim facing its attacker hits back right away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CombatSettings {
+    /// The health every player is born with, divided by its strength if strength evolves.
+    pub max_health: u32,
+    pub base_damage: u32,
+    pub damage_scaling: DamageScaling,
+    /// How much extra damage every point of the attacker's energy does, when damage scales with energy.
+    pub energy_damage: f32,
+    /// What share of the damage it would do attacking a victim facing its attacker does to the attacker.
+    pub counter_damage: f32,
+}
+
+impl Default for CombatSettings {
+    fn default() -> Self {
+        Self {
+            max_health: 100,
+            base_damage: 25,
+            damage_scaling: DamageScaling::Energy,
+            energy_damage: 0.1,
+            counter_damage: 0.5,
+        }
+    }
+}
+
+impl CombatSettings {
+    /// How much damage an attack does, depending on how much energy the attacker has and how strong it is.
+    pub fn damage(&self, energy: u32, strength: f32) -> u32 {
+        match self.damage_scaling {
+            DamageScaling::Energy => self.base_damage + (energy as f32 * self.energy_damage) as u32,
+            DamageScaling::Strength => (self.base_damage as f32 * strength) as u32,
+        }
+    }
+
+    pub fn health(&self, strength: f32) -> u32 {
+        match self.damage_scaling {
+            DamageScaling::Energy => self.max_health,
+            DamageScaling::Strength => ((self.max_health as f32 / strength) as u32).max(1),
+        }
+    }
+}
+
+// SCENTS
+
+/// How food and pheromones smell. Scents pile up on the tiles they're given off on,
+/// spread to the neighbouring tiles and fade away a little every turn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScentSettings {
+    /// How much scent a piece of food gives off every turn.
+    pub food_emission: f32,
+    pub dead_meat_emission: f32,
+    /// How much pheromone a player leaves on its tile when it deposits some.
+    pub pheromone_deposit: f32,
+    /// What share of the scent on a tile spreads to its neighbours every turn.
+    pub diffusion: f32,
+    /// What share of the food scent on a tile fades away every turn.
+    pub decay: f32,
+    pub pheromone_decay: f32,
+}
+
+impl Default for ScentSettings {
+    fn default() -> Self {
+        Self {
+            food_emission: 1.0,
+            dead_meat_emission: 2.0,
+            pheromone_deposit: 10.0,
+            diffusion: 0.5,
+            decay: 0.1,
+            pheromone_decay: 0.05,
+        }
+    }
+}
+
+// GENETICS
+
+pub const DEFAULT_BRAIN_KIND: BrainKind = BrainKind::Reflex;
+pub const DEFAULT_MUTATION_RATE: f32 = 0.02;
+/// What part of the players, fittest first, gets to breed the next generation with truncation selection.
+pub const DEFAULT_PARENT_PERCENT: u8 = 50;
+pub const DEFAULT_SELECTION: SelectionKind = SelectionKind::Truncation;
+pub const DEFAULT_TOURNAMENT_SIZE: u32 = 3;
+/// How many of the fittest players are copied into the next generation unchanged.
+pub const DEFAULT_ELITES: u32 = 0;
+pub const ENERGY_BUCKETS: usize = 3;
+/// How many internal states an FSM brain has to remember things with.
+pub const FSM_STATES: usize = 4;
+/// How deep the programs of the first generation of GP brains can grow.
+pub const GP_INITIAL_DEPTH: usize = 4;
+/// No GP program ever grows deeper than this, however it's bred or mutated.
+pub const GP_MAX_DEPTH: usize = 8;
+/// How far into the line of sight an NN brain gets inputs for, whatever the configured `los_length`.
+/// This keeps the weight vectors of every run the same length, so NN genomes stay comparable.
+pub const NN_SIGHT: usize = 5;
+pub const NN_HIDDEN: usize = 8;
+/// The standard deviation of the noise added to a mutated NN weight.
+pub const NN_MUTATION_STRENGTH: f32 = 0.5;
+pub const NEAT_ADD_CONNECTION_CHANCE: f64 = 0.05;
+pub const NEAT_ADD_NODE_CHANCE: f64 = 0.03;
+/// How likely a connection disabled in either parent is to stay disabled in the child.
+pub const NEAT_KEEP_DISABLED_CHANCE: f64 = 0.75;
+pub const NEAT_DISJOINT_COEFFICIENT: f32 = 1.0;
+pub const NEAT_WEIGHT_COEFFICIENT: f32 = 0.4;
+/// Genomes closer to a species' representative than this belong to that species.
+pub const NEAT_COMPATIBILITY_THRESHOLD: f32 = 3.0;
+/// After this many generations without improving, a species is culled.
+pub const NEAT_STAGNATION_LIMIT: u32 = 15;
+/// At most this many pairs of players are compared when measuring how diverse a generation is.
+pub const DIVERSITY_SAMPLE_PAIRS: usize = 5000;
+
+/// Raising the mutation rate when the population's genomes get too much alike, and lowering it
+/// when they're far apart, based on the mean distance between the genomes of every generation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveMutation {
+    pub enabled: bool,
+    /// Below this mean genome distance, the mutation rate goes up.
+    pub low_diversity: f32,
+    /// Above this mean genome distance, the mutation rate goes down.
+    pub high_diversity: f32,
+    /// What the mutation rate gets multiplied or divided by.
+    pub factor: f32,
+    pub min_rate: f32,
+    pub max_rate: f32,
+}
+
+impl Default for AdaptiveMutation {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            low_diversity: 0.1,
+            high_diversity: 0.5,
+            factor: 1.5,
+            min_rate: 0.005,
+            max_rate: 0.5,
+        }
+    }
+}
+
+// ISLANDS
+
+/// With more than one island, every island evolves its own population on its own board.
+pub const DEFAULT_ISLANDS: u32 = 1;
+pub const DEFAULT_MIGRATION_INTERVAL: u32 = 10;
+/// How many of its fittest genomes an island sends to each of its destinations.
+pub const DEFAULT_MIGRANTS: u32 = 5;
+pub const DEFAULT_MIGRATION_TOPOLOGY: MigrationTopology = MigrationTopology::Ring;
+
+// PERSISTENCE
+
+/// How many genomes the hall of fame keeps.
+pub const DEFAULT_HALL_OF_FAME_SIZE: u32 = 10;
+
+// RUNTIME CONFIGURATION
+
+/// Everything about the simulation that can be tweaked without recompiling.
+/// Starts out with the defaults above, then gets overridden by a TOML config file and the command line.
+/// Every field is optional in the config file: anything not mentioned keeps its default.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+    /// Seed for the simulation's random number generator. A random seed is picked if not set.
+    pub seed: Option<u64>,
+    pub grid_size: u32,
+    pub turns_per_gen: u32,
+    pub seconds_per_turn: f64,
+    /// How many players there are, as a percentage of the board's tiles.
+    pub player_percent: u8,
+    /// How much food there is, as a percentage of the player count.
+    pub food_percent: u8,
+    /// How much energy the body of a starved player is worth. With 0, starved players leave nothing behind.
+    pub starved_corpse_value: u32,
+    pub disengage_length: u32,
+    /// How far a straight or cone-shaped line of sight reaches.
+    pub los_length: u32,
+    pub los_shape: LosShape,
+    /// Let every player inherit the shape of its line of sight from its parents and mutate it,
+    /// instead of everybody seeing the configured `los_shape`.
+    pub evolve_los_shape: bool,
+    /// How wide a cone-shaped line of sight is, in degrees on either side of where the player is facing.
+    pub los_cone_half_angle: u32,
+    pub los_circle_radius: u32,
+    pub scan: ScanSettings,
+    pub scent: ScentSettings,
+    pub combat: CombatSettings,
+    pub action_costs: ActionCosts,
+    pub brain: BrainKind,
+    pub mutation_rate: f32,
+    pub adaptive_mutation: AdaptiveMutation,
+    pub parent_percent: u8,
+    pub selection: SelectionKind,
+    pub tournament_size: u32,
+    /// How many of the fittest players are copied into the next generation unchanged, whatever the selection.
+    pub elites: u32,
+    /// How much each thing a player achieved counts towards its fitness.
+    pub fitness: FitnessWeights,
+    /// How many populations evolve side by side, each on its own board. Only supported headless.
+    pub islands: u32,
+    /// Every how many generations the islands exchange migrants.
+    pub migration_interval: u32,
+    pub migrants: u32,
+    pub migration_topology: MigrationTopology,
+    pub hall_of_fame_size: u32,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            grid_size: DEFAULT_GRID_SIZE,
+            turns_per_gen: TURNS_PER_GEN,
+            seconds_per_turn: SECONDS_PER_TURN,
+            player_percent: DEFAULT_PLAYER_PERCENT,
+            food_percent: DEFAULT_FOOD_PERCENT,
+            starved_corpse_value: DEFAULT_STARVED_CORPSE_VALUE,
+            disengage_length: DISENGAGE_LENGTH,
+            los_length: DEFAULT_LOS_LENGTH,
+            los_shape: DEFAULT_LOS_SHAPE,
+            evolve_los_shape: false,
+            los_cone_half_angle: DEFAULT_LOS_CONE_HALF_ANGLE,
+            los_circle_radius: DEFAULT_LOS_CIRCLE_RADIUS,
+            scan: ScanSettings::default(),
+            scent: ScentSettings::default(),
+            combat: CombatSettings::default(),
+            action_costs: ActionCosts::default(),
+            brain: DEFAULT_BRAIN_KIND,
+            mutation_rate: DEFAULT_MUTATION_RATE,
+            adaptive_mutation: AdaptiveMutation::default(),
+            parent_percent: DEFAULT_PARENT_PERCENT,
+            selection: DEFAULT_SELECTION,
+            tournament_size: DEFAULT_TOURNAMENT_SIZE,
+            elites: DEFAULT_ELITES,
+            fitness: FitnessWeights::default(),
+            islands: DEFAULT_ISLANDS,
+            migration_interval: DEFAULT_MIGRATION_INTERVAL,
+            migrants: DEFAULT_MIGRANTS,
+            migration_topology: DEFAULT_MIGRATION_TOPOLOGY,
+            hall_of_fame_size: DEFAULT_HALL_OF_FAME_SIZE,
+        }
+    }
+}
+
+impl SimConfig {
+    /// Read the config file (if any), then apply `key=value` overrides on top of it, in order.
+    /// Overrides use TOML syntax, so nested settings look like `action_costs.kill=10`.
+    pub fn load(path: Option<&Path>, overrides: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut table = match path {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path).map_err(|e| {
+                    rerror(&format!(
+                        "Can't read config file `{}`: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                toml::from_str::<toml::Table>(&contents).map_err(|e| {
+                    rerror(&format!(
+                        "Can't parse config file `{}`: {}",
+                        path.display(),
+                        e
+                    ))
+                })?
+            }
+            None => toml::Table::new(),
+        };
+
+        for setting in overrides {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| rerror(&format!("--set expects `key=value`, got `{}`", setting)))?;
+            let parsed = toml::from_str::<toml::Table>(&format!("{} = {}", key.trim(), value))
+                .map_err(|e| rerror(&format!("Can't parse setting `{}`: {}", setting, e)))?;
+            merge_tables(&mut table, parsed);
+        }
+
+        let config: SimConfig = table
+            .try_into()
+            .map_err(|e| rerror(&format!("Invalid configuration: {}", e)))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.grid_size == 0 {
+            return Err(rerror("grid_size must be at least 1"));
+        }
+        let Some(tiles) = self.grid_size.checked_mul(self.grid_size) else {
+            return Err(rerror("grid_size is too big"));
+        };
+        if !self.seconds_per_turn.is_finite() || self.seconds_per_turn <= 0.0 {
+            return Err(rerror("seconds_per_turn must be over 0"));
+        }
+        if self.turns_per_gen < 3 {
+            return Err(rerror("turns_per_gen must be at least 3"));
+        }
+        if self.player_percent > 100 || self.food_percent > 100 || self.parent_percent > 100 {
+            return Err(rerror("percentages can't be over 100"));
+        }
+        if self.player_count() as u64 + self.food_count() as u64 > tiles as u64 {
+            return Err(rerror(
+                "there's not enough room on the board for so many players and food",
+            ));
+        }
+        if self.los_cone_half_angle > 90 {
+            return Err(rerror("los_cone_half_angle can't be over 90 degrees"));
+        }
+        let scan = &self.scan;
+        if [
+            scan.food_transparency,
+            scan.player_transparency,
+            scan.min_visibility,
+        ]
+        .iter()
+        .any(|value| !(0.0..=1.0).contains(value))
+        {
+            return Err(rerror(
+                "scan's transparencies and min_visibility must be between 0 and 1",
+            ));
+        }
+        let scent = &self.scent;
+        if [scent.diffusion, scent.decay, scent.pheromone_decay]
+            .iter()
+            .any(|value| !(0.0..=1.0).contains(value))
+        {
+            return Err(rerror(
+                "scent's diffusion and decays must be between 0 and 1",
+            ));
+        }
+        if [
+            scent.food_emission,
+            scent.dead_meat_emission,
+            scent.pheromone_deposit,
+        ]
+        .iter()
+        .any(|value| *value < 0.0)
+        {
+            return Err(rerror("scent's emissions can't be negative"));
+        }
+        if self.combat.max_health == 0 {
+            return Err(rerror("combat.max_health must be at least 1"));
+        }
+        if self.combat.energy_damage < 0.0 || self.combat.counter_damage < 0.0 {
+            return Err(rerror(
+                "combat.energy_damage and combat.counter_damage can't be negative",
+            ));
+        }
+        if self.tournament_size == 0 {
+            return Err(rerror("tournament_size must be at least 1"));
+        }
+        if self.elites > self.player_count() {
+            return Err(rerror("there can't be more elites than players"));
+        }
+        if self.islands == 0 {
+            return Err(rerror("islands must be at least 1"));
+        }
+        if !(0.0..=1.0).contains(&self.mutation_rate) {
+            return Err(rerror("mutation_rate must be between 0 and 1"));
+        }
+        let adaptive = &self.adaptive_mutation;
+        if !(0.0..=1.0).contains(&adaptive.min_rate) || !(0.0..=1.0).contains(&adaptive.max_rate) {
+            return Err(rerror("adaptive_mutation's rates must be between 0 and 1"));
+        }
+        if adaptive.min_rate > adaptive.max_rate || adaptive.low_diversity > adaptive.high_diversity
+        {
+            return Err(rerror(
+                "adaptive_mutation's minimums can't be over its maximums",
+            ));
+        }
+        if adaptive.factor < 1.0 {
+            return Err(rerror("adaptive_mutation.factor must be at least 1"));
+        }
+        if self.migration_interval == 0 {
+            return Err(rerror("migration_interval must be at least 1"));
+        }
+        Ok(())
+    }
+
+    pub fn player_count(&self) -> u32 {
+        ((self.grid_size * self.grid_size) as f32 * percent(self.player_percent)) as u32
+    }
+
+    pub fn food_count(&self) -> u32 {
+        (self.player_count() as f32 * percent(self.food_percent)) as u32
+    }
+
+    pub fn energy_min(&self) -> u32 {
+        self.turns_per_gen / 2
+    }
+
+    pub fn energy_max(&self) -> u32 {
+        (self.turns_per_gen as f32 / 1.5) as u32
+    }
+
+    pub fn food_value(&self) -> u32 {
+        self.turns_per_gen / 3 * 2
+    }
+
+    pub fn parent_ratio(&self) -> f32 {
+        percent(self.parent_percent)
+    }
+
+    pub fn energy_bucket_size(&self) -> u32 {
+        self.energy_min() / 2
+    }
+
+    /// The line of sight of a player seeing the given shape.
+    pub fn line_of_sight(&self, shape: LosShape) -> LineOfSight {
+        LineOfSight {
+            shape,
+            length: match shape {
+                LosShape::Straight | LosShape::Cone => self.los_length,
+                LosShape::Circle => self.los_circle_radius,
+            },
+            half_angle: self.los_cone_half_angle,
+        }
+    }
+
+    /// What the action costs a player whose line of sight is shaped like `los_shape`.
+    pub fn action_cost(&self, action_type: &PlayerActionType, los_shape: LosShape) -> u32 {
+        let costs = &self.action_costs;
+        match *action_type {
+            PlayerActionType::Idle => costs.idle,
+            PlayerActionType::Turn(_) => costs.turn,
+            PlayerActionType::ScanLOS => match los_shape {
+                LosShape::Straight => costs.scan_los,
+                LosShape::Cone => costs.scan_los_cone,
+                LosShape::Circle => costs.scan_los_circle,
+            },
+            PlayerActionType::Eat => costs.eat,
+            PlayerActionType::MoveForward => costs.move_forward,
+            PlayerActionType::MoveBackwards => costs.move_backwards,
+            PlayerActionType::BuildWall => costs.build_wall,
+            PlayerActionType::Kill => costs.kill,
+            PlayerActionType::DepositPheromone => costs.deposit_pheromone,
+        }
+    }
+
+    /// What seeing a line of sight shaped like `los_shape` costs every turn.
+    pub fn sight_upkeep(&self, los_shape: LosShape) -> u32 {
+        match los_shape {
+            LosShape::Straight => 0,
+            LosShape::Cone => self.action_costs.cone_sight,
+            LosShape::Circle => self.action_costs.circle_sight,
+        }
+    }
+}
+
+/// Recursively copy everything from `overrides` into `base`, replacing whatever's there.
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(override_table)) => {
+                merge_tables(base_table, override_table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::board::{
+    breed_next_generation, clear_board, rank_players, GenePool, Generation,
+};
+use crate::engine::common::VisualizerState;
+use crate::engine::config::SimConfig;
+use crate::engine::report::GenerationReport;
+use crate::simulation::genome::Genome;
+use crate::simulation::players::*;
+
+/// Which islands send their migrants where.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationTopology {
+    /// Every island sends its migrants to the next one, and the last one to the first.
+    Ring,
+    /// Every island sends its migrants to every other island.
+    Full,
+}
+
+impl MigrationTopology {
+    fn destinations(&self, island: usize, count: usize) -> Vec<usize> {
+        match self {
+            MigrationTopology::Ring => vec![(island + 1) % count],
+            MigrationTopology::Full => (0..count).filter(|other| *other != island).collect(),
+        }
+    }
+}
+
+/// One of several populations evolving side by side, each in its own app with its own board.
+/// Every `migration_interval` generations, the fittest `migrants` genomes of every island are sent over
+/// to its destinations, where they take the place of some of the offspring.
+#[derive(Resource, Debug)]
+pub struct Island {
+    pub index: usize,
+    pub count: usize,
+    /// The seed of the whole run, which the seed of every island is derived from.
+    pub run_seed: u64,
+    emigration: Vec<Sender<Vec<Genome>>>,
+}
+
+/// Where an island's migrants come from, ordered by source island so they always arrive in the same order.
+/// It's a non-send resource, which keeps waiting for migrants on the island's own thread:
+/// all islands share the same task pool, and blocking one of its threads could starve the other islands.
+pub struct Immigration {
+    sources: Vec<Receiver<Vec<Genome>>>,
+}
+
+/// Create `count` islands, connected as `topology` says.
+pub fn connect_islands(
+    count: usize,
+    topology: MigrationTopology,
+    run_seed: u64,
+) -> Vec<(Island, Immigration)> {
+    let mut islands: Vec<(Island, Immigration)> = (0..count)
+        .map(|index| {
+            let island = Island {
+                index,
+                count,
+                run_seed,
+                emigration: vec![],
+            };
+            (island, Immigration { sources: vec![] })
+        })
+        .collect();
+    for source in 0..count {
+        for destination in topology.destinations(source, count) {
+            let (sender, receiver) = channel();
+            islands[source].0.emigration.push(sender);
+            islands[destination].1.sources.push(receiver);
+        }
+    }
+    islands
+}
+
+/// Send the fittest genomes of the generation that just finished to the other islands, then wait for theirs.
+/// Every island migrates on the same generations, so waiting can't deadlock: everybody sends before they receive.
+fn migrate(
+    island: Res<Island>,
+    immigration: NonSend<Immigration>,
+    generation: Res<Generation>,
+    config: Res<SimConfig>,
+    report: Res<GenerationReport>,
+    player_query: Query<(&Vitals, &PlayerStats, &Genome), With<Player>>,
+    mut gene_pool: ResMut<GenePool>,
+) {
+    if !generation.num.is_multiple_of(config.migration_interval) {
+        return;
+    }
+
+    let emigrants: Vec<Genome> = rank_players(&player_query, &report, &config)
+        .into_iter()
+        .take(config.migrants as usize)
+        .map(|(_, genome)| genome.clone())
+        .collect();
+    for destination in island.emigration.iter() {
+        if destination.send(emigrants.clone()).is_err() {
+            warn!(
+                "Island {} couldn't send its migrants: a neighbouring island is gone.",
+                island.index + 1
+            );
+        }
+    }
+
+    let mut immigrants = vec![];
+    for source in immigration.sources.iter() {
+        match source.recv() {
+            Ok(genomes) => immigrants.extend(genomes),
+            Err(_) => warn!(
+                "Island {} didn't get any migrants: a neighbouring island is gone.",
+                island.index + 1
+            ),
+        }
+    }
+    immigrants.truncate(config.player_count() as usize);
+    let staying = gene_pool.genomes.len().saturating_sub(immigrants.len());
+    gene_pool.genomes.truncate(staying);
+    gene_pool.genomes.extend(immigrants);
+}
+
+/// Needs an `Island` resource and an `Immigration` non-send resource.
+pub struct IslandPlugin;
+
+impl Plugin for IslandPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(VisualizerState::GenerationFinished),
+            migrate.after(breed_next_generation).before(clear_board),
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::engine::board::{breed_next_generation, clear_board, Generation};
+use crate::engine::common::{StableHasher, VisualizerState};
+use crate::engine::config::SimConfig;
+use crate::engine::report::{DeathCause, GenerationReport};
+use crate::simulation::genome::Genome;
+use crate::simulation::players::*;
+
+/// How a player died, with its killer identified by genome rather than by entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineageDeath {
+    Starvation,
+    KilledBy(u64),
+}
+
+/// The birth of a genome and, once its generation is over, how its player did.
+#[derive(Debug, Clone, Serialize)]
+pub struct LineageRecord {
+    pub id: u64,
+    pub generation: u32,
+    pub parents: Vec<u64>,
+    pub fitness: Option<f32>,
+    pub stats: Option<PlayerStats>,
+    pub death: Option<LineageDeath>,
+    pub death_turn: Option<u32>,
+}
+
+/// The family tree of the run. Lineages die out all the time, so only the players of the current generation
+/// and their ancestors are kept, which is all it takes to trace where the current population came from.
+/// If there's an output directory, the tree is written to `lineage.json` and `lineage.dot` (GraphViz)
+/// in it after every generation, with the line of the generation's champion highlighted.
+#[derive(Resource, Debug, Default)]
+pub struct LineageStore {
+    output_dir: Option<PathBuf>,
+    records: HashMap<u64, LineageRecord>,
+    /// The genomes of the generation being played.
+    current: Vec<u64>,
+    /// Which player was born with which genome in the generation being played, to tell who killed whom.
+    players: HashMap<Entity, u64>,
+    births: u64,
+}
+
+/// What gets written to `lineage.json`: every genome, oldest first, with the champion's ancestry spelled out.
+#[derive(Serialize)]
+struct LineageExport<'a> {
+    champion: Option<u64>,
+    champion_ancestors: Vec<u64>,
+    genomes: Vec<&'a LineageRecord>,
+}
+
+impl LineageStore {
+    const JSON_FILE: &'static str = "lineage.json";
+    const DOT_FILE: &'static str = "lineage.dot";
+
+    pub fn new(output_dir: Option<PathBuf>) -> Self {
+        Self {
+            output_dir,
+            ..default()
+        }
+    }
+
+    /// Ids are derived from the seed of the run and the number of births so far,
+    /// so they come out the same on every replay and differ between islands.
+    fn next_id(&mut self, seed: u64) -> u64 {
+        self.births += 1;
+        let mut hasher = StableHasher::default();
+        (seed, self.births).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Give the genome of a player that's being spawned its id and put it in the family tree.
+    pub fn record_birth(
+        &mut self,
+        player: Entity,
+        genome: &mut Genome,
+        generation: u32,
+        seed: u64,
+    ) {
+        let id = self.next_id(seed);
+        genome.born(id);
+        self.records.insert(
+            id,
+            LineageRecord {
+                id,
+                generation,
+                parents: genome.parents.clone(),
+                fitness: None,
+                stats: None,
+                death: None,
+                death_turn: None,
+            },
+        );
+        self.current.push(id);
+        self.players.insert(player, id);
+    }
+
+    /// The fittest player of the generation that was just played.
+    pub fn champion(&self) -> Option<&LineageRecord> {
+        self.current
+            .iter()
+            .filter_map(|id| self.records.get(id))
+            .max_by(|a, b| {
+                a.fitness
+                    .unwrap_or(f32::MIN)
+                    .total_cmp(&b.fitness.unwrap_or(f32::MIN))
+            })
+    }
+
+    /// Every known ancestor of the genome, youngest first.
+    pub fn ancestors(&self, id: u64) -> Vec<&LineageRecord> {
+        let mut ancestors: Vec<&LineageRecord> = self
+            .reachable(&[id])
+            .into_iter()
+            .filter(|ancestor| *ancestor != id)
+            .filter_map(|ancestor| self.records.get(&ancestor))
+            .collect();
+        ancestors.sort_by_key(|record| (std::cmp::Reverse(record.generation), record.id));
+        ancestors
+    }
+
+    /// The given genomes and all of their known ancestors.
+    fn reachable(&self, ids: &[u64]) -> HashSet<u64> {
+        let mut reached = HashSet::new();
+        let mut pending = ids.to_vec();
+        while let Some(id) = pending.pop() {
+            if !reached.insert(id) {
+                continue;
+            }
+            if let Some(record) = self.records.get(&id) {
+                pending.extend(record.parents.iter().copied());
+            }
+        }
+        reached
+    }
+
+    /// Undo the births of genomes whose generation never got played here, so they can be born again in another app
+    /// as if they had never been born at all.
+    pub fn forget_births(&mut self, genomes: &mut [Genome]) {
+        for genome in genomes.iter_mut() {
+            if let Some(record) = self.records.remove(&genome.id) {
+                genome.id = 0;
+                genome.parents = record.parents;
+            }
+        }
+        self.current.clear();
+        self.players.clear();
+    }
+
+    fn export(&self) -> Result<(), Box<dyn Error>> {
+        let Some(output_dir) = &self.output_dir else {
+            return Ok(());
+        };
+        let mut genomes: Vec<&LineageRecord> = self.records.values().collect();
+        genomes.sort_by_key(|record| (record.generation, record.id));
+        let champion = self.champion().map(|record| record.id);
+        let champion_ancestors: Vec<u64> = champion
+            .map(|id| self.ancestors(id).iter().map(|record| record.id).collect())
+            .unwrap_or_default();
+
+        let champion_line: HashSet<u64> = champion
+            .iter()
+            .chain(champion_ancestors.iter())
+            .copied()
+            .collect();
+        let mut dot = String::from("digraph lineage {\n    node [shape=box, fontsize=10];\n");
+        for record in genomes.iter() {
+            let fitness = record
+                .fitness
+                .map_or(String::from("?"), |fitness| format!("{:.1}", fitness));
+            let style = match champion_line.contains(&record.id) {
+                true => ", style=filled, fillcolor=gold",
+                false => "",
+            };
+            writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\\ngeneration {}\\nfitness {}\"{}];",
+                record.id, record.id, record.generation, fitness, style
+            )?;
+            for parent in record.parents.iter() {
+                if self.records.contains_key(parent) {
+                    writeln!(dot, "    \"{}\" -> \"{}\";", parent, record.id)?;
+                }
+            }
+        }
+        dot.push_str("}\n");
+
+        let export = LineageExport {
+            champion,
+            champion_ancestors,
+            genomes,
+        };
+        fs::write(
+            output_dir.join(Self::JSON_FILE),
+            serde_json::to_string(&export)?,
+        )?;
+        fs::write(output_dir.join(Self::DOT_FILE), dot)?;
+        Ok(())
+    }
+}
+
+/// Write down how every player of the generation did, then let the lineages that died out go.
+fn close_generation(
+    mut lineage: ResMut<LineageStore>,
+    generation: Res<Generation>,
+    config: Res<SimConfig>,
+    report: Res<GenerationReport>,
+    player_query: Query<(&Vitals, &PlayerStats, &Genome), With<Player>>,
+) {
+    let fitness = config.fitness.function();
+    for (vitals, stats, genome) in player_query.iter() {
+        if vitals.status != PlayerStatus::Alive {
+            continue;
+        }
+        if let Some(record) = lineage.records.get_mut(&genome.id) {
+            record.fitness = Some(fitness.score(stats));
+            record.stats = Some(*stats);
+        }
+    }
+    for death in report.deaths.iter() {
+        let cause = match death.cause {
+            DeathCause::Starvation => LineageDeath::Starvation,
+            DeathCause::KilledBy(killer) => {
+                LineageDeath::KilledBy(lineage.players.get(&killer).copied().unwrap_or(0))
+            }
+        };
+        if let Some(record) = lineage.records.get_mut(&death.genome.id) {
+            record.fitness = Some(fitness.score(&death.stats));
+            record.stats = Some(death.stats);
+            record.death = Some(cause);
+            record.death_turn = Some(death.turn);
+        }
+    }
+
+    if let Some(champion) = lineage.champion() {
+        let ancestors = lineage.ancestors(champion.id);
+        let first_to_eat = ancestors
+            .iter()
+            .chain([&champion])
+            .filter(|record| record.stats.is_some_and(|stats| stats.food_eaten > 0))
+            .map(|record| record.generation)
+            .min();
+        debug!(
+            "The champion of generation {} has {} known ancestors, the first of its line to eat lived in generation {:?}.",
+            generation.num,
+            ancestors.len(),
+            first_to_eat
+        );
+    }
+    if let Err(e) = lineage.export() {
+        warn!(
+            "Couldn't export the lineage of generation {}: {}",
+            generation.num, e
+        );
+    }
+
+    let kept = lineage.reachable(&lineage.current);
+    lineage.records.retain(|id, _| kept.contains(id));
+    lineage.current.clear();
+    lineage.players.clear();
+}
+
+pub struct LineagePlugin;
+
+impl Plugin for LineagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LineageStore>().add_systems(
+            OnEnter(VisualizerState::GenerationFinished),
+            close_generation
+                .after(breed_next_generation)
+                .before(clear_board),
+        );
+    }
+}
@@ -2,5 +2,12 @@ pub mod actions;
 pub mod board;
 pub mod common;
 pub mod config;
+pub mod islands;
+pub mod lineage;
+pub mod options;
+pub mod persistence;
 pub mod random;
+pub mod report;
 pub mod rsystem;
+pub mod scent;
+pub mod visuals;
@@ -0,0 +1,120 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::engine::common::rerror;
+use crate::engine::config::SimConfig;
+
+/// How the simulation should be run, as requested on the command line.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Run without a window and without any graphics, as fast as the CPU allows.
+    pub headless: bool,
+    /// Stop after this many generations. Runs forever if not set.
+    pub generations: Option<u32>,
+    /// After a headless run, open the visualizer to show the last generation.
+    pub show_last: bool,
+    /// TOML file to read the simulation's configuration from.
+    pub config_path: Option<PathBuf>,
+    /// `key=value` settings applied on top of the config file, in the order they were given.
+    pub overrides: Vec<String>,
+    /// Directory to export the stats of every generation to.
+    pub output_dir: Option<PathBuf>,
+    /// File to save the players of every finished generation to, fittest first.
+    pub save_path: Option<PathBuf>,
+    /// File of saved genomes to give the players of the first generation.
+    pub load_path: Option<PathBuf>,
+    /// File keeping the fittest genomes of all time, across runs.
+    pub hall_of_fame_path: Option<PathBuf>,
+    /// Highlight the tiles every scan covers in the visualizer.
+    pub show_los: bool,
+}
+
+impl RunOptions {
+    pub const USAGE: &'static str =
+        "Usage: runger [--headless] [--generations <N>] [--show-last] [--seed <N>] [--config <FILE>] [--set <KEY=VALUE>]... [--output <DIR>] [--save <FILE>] [--load <FILE>] [--hall-of-fame <FILE>] [--show-los]";
+
+    pub fn from_args() -> Result<Self, Box<dyn Error>> {
+        let mut options = Self::default();
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--show-last" => options.show_last = true,
+                "--show-los" => options.show_los = true,
+                "--generations" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| rerror("--generations expects a number"))?;
+                    let generations: u32 = value.parse().map_err(|_| {
+                        rerror(&format!("--generations expects a number, got `{}`", value))
+                    })?;
+                    if generations == 0 {
+                        return Err(rerror("--generations must be at least 1"));
+                    }
+                    options.generations = Some(generations);
+                }
+                "--seed" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| rerror("--seed expects a number"))?;
+                    let seed: u64 = value.parse().map_err(|_| {
+                        rerror(&format!("--seed expects a number, got `{}`", value))
+                    })?;
+                    options.overrides.push(format!("seed={}", seed));
+                }
+                "--config" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| rerror("--config expects a file path"))?;
+                    options.config_path = Some(PathBuf::from(value));
+                }
+                "--output" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| rerror("--output expects a directory path"))?;
+                    options.output_dir = Some(PathBuf::from(value));
+                }
+                "--save" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| rerror("--save expects a file path"))?;
+                    options.save_path = Some(PathBuf::from(value));
+                }
+                "--load" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| rerror("--load expects a file path"))?;
+                    options.load_path = Some(PathBuf::from(value));
+                }
+                "--hall-of-fame" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| rerror("--hall-of-fame expects a file path"))?;
+                    options.hall_of_fame_path = Some(PathBuf::from(value));
+                }
+                "--set" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| rerror("--set expects `key=value`"))?;
+                    options.overrides.push(value);
+                }
+                unknown => return Err(rerror(&format!("Unknown argument `{}`", unknown))),
+            }
+        }
+
+        if options.show_last && !(options.headless && options.generations.is_some()) {
+            return Err(rerror(
+                "--show-last only makes sense together with --headless and --generations",
+            ));
+        }
+
+        Ok(options)
+    }
+
+    pub fn sim_config(&self) -> Result<SimConfig, Box<dyn Error>> {
+        SimConfig::load(self.config_path.as_deref(), &self.overrides)
+    }
+}
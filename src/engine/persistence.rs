@@ -0,0 +1,285 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::board::{breed_next_generation, clear_board, rank_players, Generation};
+use crate::engine::common::{rerror, VisualizerState};
+use crate::engine::config::SimConfig;
+use crate::engine::islands::Island;
+use crate::engine::options::RunOptions;
+use crate::engine::report::GenerationReport;
+use crate::simulation::genome::{BrainData, Genome};
+use crate::simulation::players::*;
+
+/// Bumped whenever the layout of genome files changes, so that old files get rejected instead of misread.
+pub const GENOME_FILE_VERSION: u32 = 1;
+
+/// A genome as it's saved to disk, along with how it did. Only records of the hall of fame, which can come
+/// from different runs, carry the configuration they evolved under: a saved population keeps it once for the whole file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenomeRecord {
+    pub fitness: f32,
+    pub generation: u32,
+    pub id: u64,
+    pub fingerprint: u64,
+    pub parents: Vec<u64>,
+    /// Only there if the shape of the line of sight evolved along with the brain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub los_shape: Option<LosShape>,
+    /// Only there if strength evolved along with the brain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strength: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<SimConfig>,
+    pub brain: BrainData,
+}
+
+impl GenomeRecord {
+    pub fn new(fitness: f32, generation: u32, genome: &Genome) -> Self {
+        Self {
+            fitness,
+            generation,
+            id: genome.id,
+            fingerprint: genome.fingerprint(),
+            parents: genome.parents.clone(),
+            los_shape: genome.los_shape,
+            strength: genome.strength,
+            config: None,
+            brain: genome.brain.to_data(),
+        }
+    }
+
+    pub fn genome(&self) -> Genome {
+        Genome {
+            brain: self.brain.clone().into_brain(),
+            id: self.id,
+            parents: self.parents.clone(),
+            los_shape: self.los_shape,
+            strength: self.strength,
+        }
+    }
+}
+
+/// What `--save`, `--load` and the hall of fame read and write: a versioned JSON list of genome records,
+/// along with the configuration they evolved under if they all come from the same run.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GenomeFile {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<SimConfig>,
+    pub genomes: Vec<GenomeRecord>,
+}
+
+impl GenomeFile {
+    pub fn new(config: Option<SimConfig>, genomes: Vec<GenomeRecord>) -> Self {
+        Self {
+            version: GENOME_FILE_VERSION,
+            config,
+            genomes,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+
+        let contents = fs::read_to_string(path).map_err(|e| {
+            rerror(&format!(
+                "Can't read genome file `{}`: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let invalid = |e: serde_json::Error| {
+            rerror(&format!("Invalid genome file `{}`: {}", path.display(), e))
+        };
+        let header: Header = serde_json::from_str(&contents).map_err(invalid)?;
+        if header.version != GENOME_FILE_VERSION {
+            return Err(rerror(&format!(
+                "Genome file `{}` is version {}, but only version {} is supported",
+                path.display(),
+                header.version,
+                GENOME_FILE_VERSION
+            )));
+        }
+        let file: Self = serde_json::from_str(&contents).map_err(invalid)?;
+        for record in file.genomes.iter() {
+            record.brain.validate().map_err(|e| {
+                rerror(&format!(
+                    "Invalid genome file `{}`: genome {}: {}",
+                    path.display(),
+                    record.id,
+                    e
+                ))
+            })?;
+        }
+        Ok(file)
+    }
+
+    /// Write the file next to where it belongs first, then move it into place,
+    /// so that a run killed halfway through saving doesn't leave a corrupted file behind.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, serde_json::to_string(self)?)?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
+
+/// Where island `index` (counting from 0) keeps its own copy of the file at `path`, e.g. `best_island_2.json`.
+pub fn island_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+    let file_name = match path.extension() {
+        Some(extension) => format!(
+            "{}_island_{}.{}",
+            stem,
+            index + 1,
+            extension.to_string_lossy()
+        ),
+        None => format!("{}_island_{}", stem, index + 1),
+    };
+    path.with_file_name(file_name)
+}
+
+/// The fittest genomes ever seen, across every run that used the same file, fittest first.
+/// It's written back to its file whenever a generation gets into it, so it survives the process.
+/// Fitness is only comparable between runs scoring players the same way.
+#[derive(Resource, Debug, Default)]
+pub struct HallOfFame {
+    path: Option<PathBuf>,
+    size: usize,
+    pub records: Vec<GenomeRecord>,
+}
+
+impl HallOfFame {
+    /// Pick up the hall of fame in `path` where previous runs left it, or start an empty one if there's no such file.
+    pub fn open(path: PathBuf, size: usize) -> Result<Self, Box<dyn Error>> {
+        let records = match path.exists() {
+            true => GenomeFile::load(&path)?.genomes,
+            false => vec![],
+        };
+        Ok(Self {
+            path: Some(path),
+            size,
+            records,
+        })
+    }
+
+    /// Let the best of `candidates` in, returning whether any of them made it.
+    /// A genome that's already in keeps its best fitness and the generation it got it in.
+    fn admit(&mut self, candidates: Vec<GenomeRecord>) -> bool {
+        let mut admitted = false;
+        for candidate in candidates {
+            let worst = match self.records.len() < self.size {
+                true => f32::NEG_INFINITY,
+                false => self.records.last().map_or(f32::INFINITY, |r| r.fitness),
+            };
+            if candidate.fitness <= worst {
+                break;
+            }
+            match self
+                .records
+                .iter()
+                .position(|record| record.fingerprint == candidate.fingerprint)
+            {
+                Some(index) if self.records[index].fitness >= candidate.fitness => continue,
+                Some(index) => {
+                    self.records.remove(index);
+                }
+                None => {}
+            }
+            let place = self
+                .records
+                .partition_point(|record| record.fitness >= candidate.fitness);
+            self.records.insert(place, candidate);
+            self.records.truncate(self.size);
+            admitted = true;
+        }
+        admitted
+    }
+}
+
+fn update_hall_of_fame(
+    mut hall_of_fame: ResMut<HallOfFame>,
+    generation: Res<Generation>,
+    config: Res<SimConfig>,
+    report: Res<GenerationReport>,
+    player_query: Query<(&Vitals, &PlayerStats, &Genome), With<Player>>,
+) {
+    let Some(path) = hall_of_fame.path.clone() else {
+        return;
+    };
+    let candidates = rank_players(&player_query, &report, &config)
+        .into_iter()
+        .take(hall_of_fame.size)
+        .map(|(fitness, genome)| GenomeRecord {
+            config: Some(config.clone()),
+            ..GenomeRecord::new(fitness, generation.num, genome)
+        })
+        .collect();
+    if !hall_of_fame.admit(candidates) {
+        return;
+    }
+    if let Err(e) = GenomeFile::new(None, hall_of_fame.records.clone()).save(&path) {
+        warn!(
+            "Couldn't save the hall of fame to `{}`: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Overwritten at the end of every generation, so the file always holds the latest finished generation.
+/// Every island saves its own population to its own file.
+fn save_population(
+    options: Res<RunOptions>,
+    island: Option<Res<Island>>,
+    generation: Res<Generation>,
+    config: Res<SimConfig>,
+    report: Res<GenerationReport>,
+    player_query: Query<(&Vitals, &PlayerStats, &Genome), With<Player>>,
+) {
+    let Some(path) = &options.save_path else {
+        return;
+    };
+    let path = match island {
+        Some(island) => island_path(path, island.index),
+        None => path.clone(),
+    };
+    let records = rank_players(&player_query, &report, &config)
+        .into_iter()
+        .map(|(fitness, genome)| GenomeRecord::new(fitness, generation.num, genome))
+        .collect();
+    if let Err(e) = GenomeFile::new(Some(config.clone()), records).save(&path) {
+        warn!(
+            "Couldn't save generation {} to `{}`: {}",
+            generation.num,
+            path.display(),
+            e
+        );
+    }
+}
+
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HallOfFame>().add_systems(
+            OnEnter(VisualizerState::GenerationFinished),
+            (update_hall_of_fame, save_population)
+                .after(breed_next_generation)
+                .before(clear_board),
+        );
+    }
+}
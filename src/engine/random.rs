@@ -1,23 +1,68 @@
-use rand::{thread_rng, Rng};
+use bevy::prelude::*;
+use rand::{thread_rng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
     engine::config::*,
     simulation::players::{FacingDirection, PlayerActionType},
 };
 
-pub fn random_board_pos() -> (u32, u32) {
-    let mut rng = thread_rng();
+/// The one source of randomness for everything that happens in the simulation.
+/// Given the same seed, a run produces exactly the same boards, actions and survival numbers.
+#[derive(Resource, Debug, Clone)]
+pub struct SimRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl SimRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Seed from the given value if there is one, or pick a fresh seed otherwise.
+    pub fn new(seed: Option<u64>) -> Self {
+        Self::from_seed(seed.unwrap_or_else(|| thread_rng().gen()))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A separate generator seeded from this one. Whatever is drawn from the fork doesn't affect
+    /// what this generator produces next, so e.g. different kinds of brains can be born on identical boards.
+    pub fn fork(&mut self) -> Self {
+        Self::from_seed(self.rng.gen())
+    }
+}
 
-    (
-        rng.gen_range(0..DEFAULT_GRID_SIZE),
-        rng.gen_range(0..DEFAULT_GRID_SIZE),
-    )
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
 }
 
-pub fn random_player_action() -> PlayerActionType {
-    let mut rng = thread_rng();
+pub fn random_board_pos<R: Rng + ?Sized>(width: u32, height: u32, rng: &mut R) -> (u32, u32) {
+    (rng.gen_range(0..width), rng.gen_range(0..height))
+}
 
-    let action_num = rng.gen_range(0..8);
+pub fn random_player_action<R: Rng + ?Sized>(rng: &mut R) -> PlayerActionType {
+    let action_num = rng.gen_range(0..9);
     match action_num {
         0 => PlayerActionType::Idle,
         1 => PlayerActionType::MoveForward,
@@ -27,11 +72,18 @@ pub fn random_player_action() -> PlayerActionType {
         5 => PlayerActionType::Kill,
         6 => PlayerActionType::BuildWall,
         7 => PlayerActionType::ScanLOS,
+        8 => PlayerActionType::DepositPheromone,
         _ => unreachable!("{} is not allowed in random_action_type()", action_num),
     }
 }
 
-pub fn random_energy_start() -> u32 {
-    let mut rng = thread_rng();
-    rng.gen_range(default_energy_min()..=default_energy_max())
+/// A sample from the standard normal distribution, using the Box-Muller transform.
+pub fn random_gaussian<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    let u1: f32 = 1.0 - rng.gen::<f32>();
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+}
+
+pub fn random_energy_start<R: Rng + ?Sized>(config: &SimConfig, rng: &mut R) -> u32 {
+    rng.gen_range(config.energy_min()..=config.energy_max())
 }
@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Serialize;
+
+use crate::engine::board::{Generation, MutationRate};
+use crate::engine::common::{rerror, BoardPosition, VisualizerState};
+use crate::engine::config::DIVERSITY_SAMPLE_PAIRS;
+use crate::engine::random::SimRng;
+use crate::simulation::genome::Genome;
+use crate::simulation::players::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeathCause {
+    Starvation,
+    KilledBy(Entity),
+}
+
+/// A death, along with what the player had achieved and its genes,
+/// so dead players can still be judged and bred from once the generation is over.
+#[derive(Event, Debug, Clone)]
+pub struct DeathEvent {
+    pub player_id: Entity,
+    pub cause: DeathCause,
+    pub turn: u32,
+    pub pos: BoardPosition,
+    pub stats: PlayerStats,
+    pub genome: Genome,
+}
+
+/// Everything that happened to the players of the current generation.
+/// Cleared when the next generation starts.
+#[derive(Resource, Debug, Default)]
+pub struct GenerationReport {
+    pub deaths: Vec<DeathEvent>,
+    /// How many pieces of food got eaten up.
+    pub food_eaten: u32,
+    pub walls_built: u32,
+    /// How many times each action was actually carried out.
+    pub actions: HashMap<PlayerActionType, u32>,
+    /// The share of distinct genomes in the generation when it was born, from 0 (all clones) to 1.
+    pub genome_diversity: f32,
+    pub unique_genomes: u32,
+    /// The mean distance between two genomes of the generation, from 0 (all clones) to 1.
+    pub mean_genome_distance: f32,
+}
+
+impl GenerationReport {
+    pub fn starved(&self) -> u32 {
+        self.count(|cause| cause == DeathCause::Starvation)
+    }
+
+    pub fn killed(&self) -> u32 {
+        self.count(|cause| matches!(cause, DeathCause::KilledBy(_)))
+    }
+
+    /// Deaths that were neither starvation nor a kill. There are no such deaths yet, but the stats keep a column for them.
+    pub fn died_otherwise(&self) -> u32 {
+        self.deaths.len() as u32 - self.starved() - self.killed()
+    }
+
+    fn count(&self, predicate: impl Fn(DeathCause) -> bool) -> u32 {
+        self.deaths
+            .iter()
+            .filter(|death| predicate(death.cause))
+            .count() as u32
+    }
+}
+
+/// Runs last thing in the frame, so every death of the frame is in the report
+/// by the time the generation is over.
+fn record_deaths(mut death_events: EventReader<DeathEvent>, mut report: ResMut<GenerationReport>) {
+    for event in death_events.read() {
+        debug!(
+            "Player {:?} died on turn {} at ({}, {}): {:?}",
+            event.player_id, event.turn, event.pos.x, event.pos.y, event.cause
+        );
+        report.deaths.push(event.clone());
+    }
+}
+
+/// The mean distance between the items, over every pair of them or, if there are too many pairs,
+/// over `DIVERSITY_SAMPLE_PAIRS` random ones. Pairs are sampled with a generator of their own,
+/// seeded from the run's seed and the generation, so measuring doesn't change how the simulation plays out.
+fn mean_pairwise_distance<T>(
+    items: &[T],
+    distance: impl Fn(&T, &T) -> f32,
+    rng: &SimRng,
+    generation: u32,
+) -> f32 {
+    let count = items.len();
+    if count < 2 {
+        return 0.0;
+    }
+    let pairs = count * (count - 1) / 2;
+    if pairs <= DIVERSITY_SAMPLE_PAIRS {
+        let total: f32 = (0..count)
+            .flat_map(|i| (i + 1..count).map(move |j| (i, j)))
+            .map(|(i, j)| distance(&items[i], &items[j]))
+            .sum();
+        return total / pairs as f32;
+    }
+    let mut sampler = SimRng::from_seed(rng.seed().wrapping_add(generation as u64));
+    let total: f32 = (0..DIVERSITY_SAMPLE_PAIRS)
+        .map(|_| {
+            let i = sampler.gen_range(0..count);
+            let j = (i + sampler.gen_range(1..count)) % count;
+            distance(&items[i], &items[j])
+        })
+        .sum();
+    total / DIVERSITY_SAMPLE_PAIRS as f32
+}
+
+/// Newly born players are the whole new generation, so that's when its genotypic diversity is measured.
+fn measure_genome_diversity(
+    mut report: ResMut<GenerationReport>,
+    generation: Res<Generation>,
+    rng: Res<SimRng>,
+    genome_query: Query<&Genome, Added<Genome>>,
+) {
+    let genomes: Vec<&Genome> = genome_query.iter().collect();
+    if genomes.is_empty() {
+        return;
+    }
+    let distinct: HashSet<u64> = genomes.iter().map(|genome| genome.fingerprint()).collect();
+    report.unique_genomes = distinct.len() as u32;
+    report.genome_diversity = distinct.len() as f32 / genomes.len() as f32;
+    report.mean_genome_distance =
+        mean_pairwise_distance(&genomes, |a, b| a.distance(b), &rng, generation.num);
+}
+
+/// How differently two players behaved: the total variation distance between the shares of their turns
+/// they spent on each action, from 0 (exactly the same habits) to 1 (not a single action in common).
+fn behaviour_distance(a: &PlayerStats, b: &PlayerStats) -> f32 {
+    let (a_total, b_total) = (
+        a.actions.iter().sum::<u32>().max(1) as f32,
+        b.actions.iter().sum::<u32>().max(1) as f32,
+    );
+    a.actions
+        .iter()
+        .zip(b.actions.iter())
+        .map(|(a, b)| (*a as f32 / a_total - *b as f32 / b_total).abs())
+        .sum::<f32>()
+        / 2.0
+}
+
+fn clear_report(mut report: ResMut<GenerationReport>) {
+    report.deaths.clear();
+    report.food_eaten = 0;
+    report.walls_built = 0;
+    report.actions.clear();
+}
+
+/// The metrics of a finished generation, as they get exported.
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationStats {
+    pub generation: u32,
+    pub population: u32,
+    pub survivors: u32,
+    pub kills: u32,
+    pub starvations: u32,
+    pub other_deaths: u32,
+    pub food_eaten: u32,
+    pub walls_built: u32,
+    /// Energy of the survivors.
+    pub mean_energy: f32,
+    pub max_energy: u32,
+    pub genome_diversity: f32,
+    pub unique_genomes: u32,
+    pub mean_genome_distance: f32,
+    /// The mean difference between the action habits of two players of the generation, from 0 to 1.
+    pub behaviour_diversity: f32,
+    /// The mutation rate the generation was bred with.
+    pub mutation_rate: f32,
+    pub actions: BTreeMap<&'static str, u32>,
+}
+
+impl GenerationStats {
+    const CSV_HEADER: &'static str = "generation,population,survivors,kills,starvations,other_deaths,food_eaten,walls_built,mean_energy,max_energy,genome_diversity,unique_genomes,mean_genome_distance,behaviour_diversity,mutation_rate";
+
+    fn csv_header() -> String {
+        PlayerActionType::ALL
+            .iter()
+            .fold(Self::CSV_HEADER.to_string(), |header, action| {
+                format!("{},actions_{}", header, action.name())
+            })
+    }
+
+    fn csv_row(&self) -> String {
+        let row = format!(
+            "{},{},{},{},{},{},{},{},{:.2},{},{:.4},{},{:.4},{:.4},{:.4}",
+            self.generation,
+            self.population,
+            self.survivors,
+            self.kills,
+            self.starvations,
+            self.other_deaths,
+            self.food_eaten,
+            self.walls_built,
+            self.mean_energy,
+            self.max_energy,
+            self.genome_diversity,
+            self.unique_genomes,
+            self.mean_genome_distance,
+            self.behaviour_diversity,
+            self.mutation_rate
+        );
+        PlayerActionType::ALL.iter().fold(row, |row, action| {
+            format!("{},{}", row, self.actions[action.name()])
+        })
+    }
+}
+
+/// The stats of every generation of the run so far.
+/// If there's an output directory, each generation is also appended to `generations.csv`
+/// and the whole history is written to `generations.json` in it.
+#[derive(Resource, Debug, Default)]
+pub struct StatsLog {
+    output_dir: Option<PathBuf>,
+    pub generations: Vec<GenerationStats>,
+}
+
+impl StatsLog {
+    const CSV_FILE: &'static str = "generations.csv";
+    const JSON_FILE: &'static str = "generations.json";
+
+    /// Start a fresh log in `output_dir`, replacing the stats of any previous run there.
+    pub fn create(output_dir: PathBuf) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(&output_dir).map_err(|e| {
+            rerror(&format!(
+                "Can't create output directory `{}`: {}",
+                output_dir.display(),
+                e
+            ))
+        })?;
+        let log = Self {
+            output_dir: Some(output_dir),
+            generations: vec![],
+        };
+        log.write(
+            Self::CSV_FILE,
+            &format!("{}\n", GenerationStats::csv_header()),
+            false,
+        )?;
+        log.write(Self::JSON_FILE, "[]\n", false)?;
+        Ok(log)
+    }
+
+    fn write(&self, file_name: &str, contents: &str, append: bool) -> Result<(), Box<dyn Error>> {
+        let Some(output_dir) = &self.output_dir else {
+            return Ok(());
+        };
+        let path = output_dir.join(file_name);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    fn push(&mut self, stats: GenerationStats) -> Result<(), Box<dyn Error>> {
+        self.write(Self::CSV_FILE, &format!("{}\n", stats.csv_row()), true)?;
+        self.generations.push(stats);
+        self.write(
+            Self::JSON_FILE,
+            &serde_json::to_string_pretty(&self.generations)?,
+            false,
+        )
+    }
+}
+
+pub fn record_generation_stats(
+    generation: Res<Generation>,
+    report: Res<GenerationReport>,
+    mut stats_log: ResMut<StatsLog>,
+    rng: Res<SimRng>,
+    mutation_rate: Res<MutationRate>,
+    player_query: Query<(&Vitals, &PlayerStats), With<Player>>,
+) {
+    let survivor_energy: Vec<u32> = player_query
+        .iter()
+        .filter(|(vitals, _)| vitals.status == PlayerStatus::Alive)
+        .map(|(vitals, _)| vitals.energy.value)
+        .collect();
+    let survivors = survivor_energy.len() as u32;
+    let behaviours: Vec<&PlayerStats> = player_query
+        .iter()
+        .filter(|(vitals, _)| vitals.status == PlayerStatus::Alive)
+        .map(|(_, stats)| stats)
+        .chain(report.deaths.iter().map(|death| &death.stats))
+        .collect();
+
+    let stats = GenerationStats {
+        generation: generation.num,
+        population: survivors + report.deaths.len() as u32,
+        survivors,
+        kills: report.killed(),
+        starvations: report.starved(),
+        other_deaths: report.died_otherwise(),
+        food_eaten: report.food_eaten,
+        walls_built: report.walls_built,
+        mean_energy: survivor_energy.iter().sum::<u32>() as f32 / survivors.max(1) as f32,
+        max_energy: survivor_energy.iter().copied().max().unwrap_or(0),
+        genome_diversity: report.genome_diversity,
+        unique_genomes: report.unique_genomes,
+        mean_genome_distance: report.mean_genome_distance,
+        behaviour_diversity: mean_pairwise_distance(
+            &behaviours,
+            |a, b| behaviour_distance(a, b),
+            &rng,
+            generation.num,
+        ),
+        mutation_rate: mutation_rate.value,
+        actions: PlayerActionType::ALL
+            .iter()
+            .map(|action| (action.name(), *report.actions.get(action).unwrap_or(&0)))
+            .collect(),
+    };
+    if let Err(e) = stats_log.push(stats) {
+        warn!(
+            "Couldn't export the stats of generation {}: {}",
+            generation.num, e
+        );
+    }
+}
+
+pub struct ReportPlugin;
+
+impl Plugin for ReportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DeathEvent>()
+            .init_resource::<GenerationReport>()
+            .init_resource::<StatsLog>()
+            .add_systems(Last, (record_deaths, measure_genome_diversity))
+            .add_systems(OnExit(VisualizerState::GenerationFinished), clear_report);
+    }
+}
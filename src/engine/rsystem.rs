@@ -1,6 +1,10 @@
+use bevy::log::LogPlugin;
 use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
 use bevy::window::{PresentMode, WindowResolution};
 
+use crate::engine::board::TurnPacing;
+
 fn camera_setup(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 }
@@ -23,3 +27,35 @@ impl Plugin for BaseSystemPlugin {
             }));
     }
 }
+
+/// The bare minimum needed to run the simulation without a window or any rendering.
+/// Turns are played back to back instead of being paced by a timer.
+pub struct HeadlessSystemPlugin {
+    /// Logging is set up once for the whole process, so when several apps run side by side only one of them does it.
+    pub logging: bool,
+}
+
+impl Plugin for HeadlessSystemPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TurnPacing::every_frame())
+            .add_plugins(MinimalPlugins)
+            .add_plugins(StatesPlugin);
+        if self.logging {
+            app.add_plugins(LogPlugin::default());
+        }
+    }
+}
+
+/// Drive a headless app by hand until it asks to exit, keeping it around afterwards
+/// so that whatever it ended up with can still be read from its world.
+pub fn run_headless(app: &mut App) -> AppExit {
+    app.finish();
+    app.cleanup();
+
+    loop {
+        app.update();
+        if let Some(exit) = app.should_exit() {
+            return exit;
+        }
+    }
+}
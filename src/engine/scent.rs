@@ -0,0 +1,164 @@
+use bevy::prelude::*;
+
+use crate::engine::actions::advance_players;
+use crate::engine::board::{advance_turn, breed_next_generation, clear_board, turn_due};
+use crate::engine::common::*;
+use crate::engine::config::SimConfig;
+use crate::simulation::players::Food;
+
+/// The two kinds of scent players can smell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScentLayer {
+    /// Given off by food, and even more so by dead bodies.
+    Food,
+    /// Left behind by players on purpose.
+    Pheromone,
+}
+
+/// How strong every kind of scent is on every tile of the board, laid out like the board's tiles.
+/// Scents spread to the neighbouring tiles and fade a little every turn, so food can be smelled from afar,
+/// and the closer it is, the stronger it smells.
+#[derive(Resource, Debug, Clone)]
+pub struct ScentField {
+    width: u32,
+    height: u32,
+    food: Vec<f32>,
+    pheromone: Vec<f32>,
+}
+
+impl FromWorld for ScentField {
+    fn from_world(world: &mut World) -> Self {
+        let grid_size = world.resource::<SimConfig>().grid_size;
+        Self::new(grid_size, grid_size)
+    }
+}
+
+impl ScentField {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            food: vec![0.0; (width * height) as usize],
+            pheromone: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, pos: &BoardPosition) -> Option<usize> {
+        if pos.x < self.width && pos.y < self.height {
+            Some((pos.y * self.width + pos.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn layer(&self, layer: ScentLayer) -> &Vec<f32> {
+        match layer {
+            ScentLayer::Food => &self.food,
+            ScentLayer::Pheromone => &self.pheromone,
+        }
+    }
+
+    fn layer_mut(&mut self, layer: ScentLayer) -> &mut Vec<f32> {
+        match layer {
+            ScentLayer::Food => &mut self.food,
+            ScentLayer::Pheromone => &mut self.pheromone,
+        }
+    }
+
+    /// How strong the scent is on the tile, 0 beyond the edge of the board.
+    pub fn at(&self, layer: ScentLayer, pos: &BoardPosition) -> f32 {
+        self.index(pos)
+            .map_or(0.0, |index| self.layer(layer)[index])
+    }
+
+    pub fn add(&mut self, layer: ScentLayer, pos: &BoardPosition, amount: f32) {
+        if let Some(index) = self.index(pos) {
+            self.layer_mut(layer)[index] += amount;
+        }
+    }
+
+    /// Every tile hands `diffusion` of its scent out to its neighbours in equal parts,
+    /// then `decay` of whatever is on it fades away.
+    fn spread(&mut self, layer: ScentLayer, diffusion: f32, decay: f32) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let values = self.layer_mut(layer);
+        let mut spread = vec![0.0; values.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let neighbours = [
+                    (x > 0).then(|| y * width + x - 1),
+                    (x + 1 < width).then(|| y * width + x + 1),
+                    (y > 0).then(|| (y - 1) * width + x),
+                    (y + 1 < height).then(|| (y + 1) * width + x),
+                ];
+                let count = neighbours.iter().flatten().count();
+                let index = y * width + x;
+                let handed_out = match count {
+                    0 => 0.0,
+                    _ => values[index] * diffusion,
+                };
+                spread[index] += values[index] - handed_out;
+                for neighbour in neighbours.iter().flatten() {
+                    spread[*neighbour] += handed_out / count as f32;
+                }
+            }
+        }
+        for (value, spread) in values.iter_mut().zip(spread) {
+            *value = spread * (1.0 - decay);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.food.fill(0.0);
+        self.pheromone.fill(0.0);
+    }
+}
+
+/// Runs at the start of every turn, so the players smell the scents of the turn they're acting in.
+fn spread_scents(
+    mut scents: ResMut<ScentField>,
+    config: Res<SimConfig>,
+    food_query: Query<(&BoardPosition, &FoodType), With<Food>>,
+) {
+    let settings = &config.scent;
+    for (pos, food_type) in food_query.iter() {
+        let emission = match food_type {
+            FoodType::Meal => settings.food_emission,
+            FoodType::DeadMeat(_) => settings.dead_meat_emission,
+        };
+        scents.add(ScentLayer::Food, pos, emission);
+    }
+    scents.spread(ScentLayer::Food, settings.diffusion, settings.decay);
+    scents.spread(
+        ScentLayer::Pheromone,
+        settings.diffusion,
+        settings.pheromone_decay,
+    );
+}
+
+/// Every generation starts out on a board that doesn't smell of anything.
+fn clear_scents(mut scents: ResMut<ScentField>) {
+    scents.clear();
+}
+
+pub struct ScentPlugin;
+
+impl Plugin for ScentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScentField>()
+            .add_systems(
+                Update,
+                spread_scents
+                    .after(advance_turn)
+                    .before(advance_players)
+                    .run_if(in_state(VisualizerState::SimulationRunning))
+                    .run_if(turn_due),
+            )
+            .add_systems(
+                OnEnter(VisualizerState::GenerationFinished),
+                clear_scents
+                    .after(breed_next_generation)
+                    .before(clear_board),
+            );
+    }
+}
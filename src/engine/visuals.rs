@@ -0,0 +1,282 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+use bevy::transform::TransformSystem;
+
+use std::collections::HashMap;
+
+use crate::engine::actions::{LOSReportEvent, LOSScannedEvent};
+use crate::engine::board::TurnPacing;
+use crate::engine::common::*;
+use crate::engine::config::*;
+use crate::engine::options::RunOptions;
+use crate::simulation::players::*;
+
+#[derive(Bundle)]
+pub struct BoardTileBundle {
+    pub sprite: SpriteBundle,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct RestoreColorsEvent {
+    pub entity_id: Entity,
+    pub old_color: Color,
+}
+
+/// The sprite of every tile of the board, by position.
+#[derive(Resource, Debug, Default)]
+pub struct TileSprites {
+    pub tiles: HashMap<BoardPosition, Entity>,
+}
+
+pub fn facing_rotation(facing: &FacingDirection) -> Quat {
+    match facing {
+        FacingDirection::Up => Quat::IDENTITY,
+        FacingDirection::Right => Quat::from_rotation_z(-PI / 2.0),
+        FacingDirection::Down => Quat::from_rotation_z(PI),
+        FacingDirection::Left => Quat::from_rotation_z(PI / 2.0),
+    }
+}
+
+fn spawn_board_tiles(mut commands: Commands, board: Res<Board>) {
+    let mut tile_sprites = TileSprites::default();
+    for (pos, _) in board.tiles() {
+        let tile = commands.spawn((
+            BoardTileBundle {
+                sprite: SpriteBundle {
+                    sprite: Sprite {
+                        color: DEFAULT_TILE_COLOR,
+                        custom_size: Some(Vec2::new(DEFAULT_TILE_SIZE, DEFAULT_TILE_SIZE)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(
+                        grid_to_world(pos.x, board.width()),
+                        grid_to_world(pos.y, board.height()),
+                        0.0,
+                    ),
+                    ..Default::default()
+                },
+            },
+            BoardTile,
+        ));
+        tile_sprites.tiles.insert(pos, tile.id());
+    }
+    commands.insert_resource(tile_sprites);
+}
+
+fn attach_player_sprites(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    player_query: Query<(Entity, &BoardPosition, &FacingDirection), Added<Player>>,
+    board: Res<Board>,
+) {
+    for (player_id, pos, facing) in player_query.iter() {
+        let triangle = Mesh2dHandle(meshes.add(Triangle2d::new(
+            Vec2::new(0., default_entity_size() / 2.),
+            Vec2::new(-default_entity_size() / 2., -default_entity_size() / 2.),
+            Vec2::new(default_entity_size() / 2., -default_entity_size() / 2.),
+        )));
+        commands
+            .entity(player_id)
+            .insert(MaterialMesh2dBundle::<ColorMaterial> {
+                mesh: triangle,
+                material: materials.add(DEFAULT_PLAYER_COLOR),
+                transform: Transform::from_xyz(
+                    grid_to_world(pos.x, board.width()),
+                    grid_to_world(pos.y, board.height()),
+                    1.0,
+                )
+                .with_rotation(facing_rotation(facing)),
+                ..default()
+            });
+    }
+}
+
+fn attach_food_sprites(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    food_query: Query<(Entity, &BoardPosition, &FoodType), Added<Food>>,
+    board: Res<Board>,
+) {
+    for (food_id, pos, food_type) in food_query.iter() {
+        let food_color = match food_type {
+            FoodType::Meal => DEFAULT_FOOD_COLOR,
+            FoodType::DeadMeat(_) => DEAD_MEAT_COLOR,
+        };
+        let mesh = meshes.add(Circle {
+            radius: default_entity_size() / 2.,
+        });
+        commands
+            .entity(food_id)
+            .insert(MaterialMesh2dBundle::<ColorMaterial> {
+                mesh: Mesh2dHandle(mesh),
+                material: materials.add(food_color),
+                transform: Transform::from_xyz(
+                    grid_to_world(pos.x, board.width()),
+                    grid_to_world(pos.y, board.height()),
+                    0.9,
+                ),
+                ..default()
+            });
+    }
+}
+
+fn attach_wall_sprites(
+    mut commands: Commands,
+    wall_query: Query<(Entity, &BoardPosition), Added<Wall>>,
+    board: Res<Board>,
+) {
+    for (wall_id, pos) in wall_query.iter() {
+        commands.entity(wall_id).insert(SpriteBundle {
+            sprite: Sprite {
+                color: DEFAULT_WALL_COLOR,
+                custom_size: Some(Vec2::new(default_entity_size(), default_entity_size())),
+                ..default()
+            },
+            transform: Transform::from_xyz(
+                grid_to_world(pos.x, board.width()),
+                grid_to_world(pos.y, board.height()),
+                0.1,
+            ),
+            ..Default::default()
+        });
+    }
+}
+
+/// Keep player graphics in line with where the players are on the board and where they're facing.
+fn sync_player_transforms(
+    board: Res<Board>,
+    mut player_query: Query<
+        (&BoardPosition, &FacingDirection, &mut Transform),
+        (
+            With<Player>,
+            Or<(Changed<BoardPosition>, Changed<FacingDirection>)>,
+        ),
+    >,
+) {
+    for (pos, facing, mut transform) in player_query.iter_mut() {
+        transform.translation = Vec3::new(
+            grid_to_world(pos.x, board.width()),
+            grid_to_world(pos.y, board.height()),
+            1.0,
+        );
+        transform.rotation = facing_rotation(facing);
+    }
+}
+
+fn player_los_report_listener(
+    mut los_report_events: EventReader<LOSReportEvent>,
+    mut restore_colors_event: EventWriter<RestoreColorsEvent>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut color_query: Query<&mut Handle<ColorMaterial>>,
+) {
+    for event in los_report_events.read() {
+        if let Ok(mut scanner_color) = color_query.get_mut(event.scanner_id) {
+            let current_color = materials.get(scanner_color.as_ref()).unwrap().color;
+            if current_color != DEFAULT_COLOR_ON_LOS_DETECT {
+                restore_colors_event.send(RestoreColorsEvent {
+                    entity_id: event.scanner_id,
+                    old_color: current_color,
+                });
+                *scanner_color = materials.add(DEFAULT_COLOR_ON_LOS_DETECT);
+            }
+        }
+        let scanned_id = match event.scanned_type {
+            OccupantType::Player(player_id) => player_id,
+            OccupantType::Food(food_id) => food_id,
+            OccupantType::Wall(wall_id) => wall_id,
+            err_occ => unreachable!(
+                "Changing color on LOS: this entity type should not be scanned: {:?}",
+                err_occ
+            ),
+        };
+        if let Ok(mut scanned_color) = color_query.get_mut(scanned_id) {
+            let current_color = materials.get(scanned_color.as_ref()).unwrap().color;
+            if current_color != DEFAULT_COLOR_ON_LOS_DETECT {
+                restore_colors_event.send(RestoreColorsEvent {
+                    entity_id: scanned_id,
+                    old_color: current_color,
+                });
+                *scanned_color = materials.add(DEFAULT_COLOR_ON_LOS_DETECT);
+            }
+        }
+    }
+}
+
+/// With `--show-los`, the tiles scanned during a turn stay highlighted until the next turn starts.
+fn show_los_tiles(
+    mut los_scanned_events: EventReader<LOSScannedEvent>,
+    mut highlighted: Local<Vec<Entity>>,
+    pacing: Res<TurnPacing>,
+    tile_sprites: Res<TileSprites>,
+    mut sprite_query: Query<&mut Sprite, With<BoardTile>>,
+) {
+    if pacing.turn_due() {
+        for tile in highlighted.drain(..) {
+            if let Ok(mut sprite) = sprite_query.get_mut(tile) {
+                sprite.color = DEFAULT_TILE_COLOR;
+            }
+        }
+    }
+    for event in los_scanned_events.read() {
+        for pos in event.tiles.iter() {
+            let Some(tile) = tile_sprites.tiles.get(pos) else {
+                continue;
+            };
+            if let Ok(mut sprite) = sprite_query.get_mut(*tile) {
+                sprite.color = DEFAULT_LOS_TILE_COLOR;
+                highlighted.push(*tile);
+            }
+        }
+    }
+}
+
+/// Colors changed during a turn are restored once the next turn starts, so they stay visible for a whole turn.
+fn restore_colors_listener(
+    mut restore_colors_events: EventReader<RestoreColorsEvent>,
+    mut pending: Local<Vec<RestoreColorsEvent>>,
+    pacing: Res<TurnPacing>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut color_query: Query<&mut Handle<ColorMaterial>>,
+) {
+    pending.extend(restore_colors_events.read().cloned());
+    if !pacing.turn_due() {
+        return;
+    }
+    for event in pending.drain(..) {
+        if let Ok(mut color) = color_query.get_mut(event.entity_id) {
+            *color = materials.add(event.old_color);
+        }
+    }
+}
+
+/// Everything that's only needed when someone is watching the simulation:
+/// sprites for the board and its occupants, and the color effects of the simulation's events.
+/// None of it is added when running headless.
+pub struct VisualsPlugin;
+
+impl Plugin for VisualsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RestoreColorsEvent>()
+            .add_systems(Startup, spawn_board_tiles)
+            .add_systems(
+                PostUpdate,
+                (
+                    (
+                        attach_player_sprites,
+                        attach_food_sprites,
+                        attach_wall_sprites,
+                    ),
+                    sync_player_transforms,
+                    restore_colors_listener,
+                    player_los_report_listener,
+                    show_los_tiles.run_if(|options: Res<RunOptions>| options.show_los),
+                )
+                    .chain()
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
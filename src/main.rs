@@ -1,17 +1,283 @@
-#[allow(clippy::type_complexity)]
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 mod engine;
 mod simulation;
 
+use std::error::Error;
+use std::thread;
+
 use bevy::prelude::*;
 
 use engine::actions::PlayerActionPlugin;
-use engine::board::GameBoardPlugin;
-use engine::rsystem::BaseSystemPlugin;
+use engine::board::{GameBoardPlugin, GenePool, Generation, MutationRate};
+use engine::config::SimConfig;
+use engine::islands::{connect_islands, IslandPlugin};
+use engine::lineage::{LineagePlugin, LineageStore};
+use engine::options::RunOptions;
+use engine::persistence::{island_path, GenomeFile, GenomeRecord, HallOfFame, PersistencePlugin};
+use engine::random::SimRng;
+use engine::report::{ReportPlugin, StatsLog};
+use engine::rsystem::{run_headless, BaseSystemPlugin, HeadlessSystemPlugin};
+use engine::scent::ScentPlugin;
+use engine::visuals::VisualsPlugin;
+use simulation::genome::Genome;
+use simulation::neat::SpeciesRegistry;
 
-fn main() {
-    App::new()
+fn visual_app(options: RunOptions, config: SimConfig) -> App {
+    let mut app = App::new();
+    app.insert_resource(SimRng::new(config.seed))
+        .insert_resource(config)
         .add_plugins(BaseSystemPlugin)
         .add_plugins(GameBoardPlugin)
         .add_plugins(PlayerActionPlugin)
-        .run();
+        .add_plugins(ReportPlugin)
+        .add_plugins(PersistencePlugin)
+        .add_plugins(LineagePlugin)
+        .add_plugins(ScentPlugin)
+        .add_plugins(VisualsPlugin)
+        .insert_resource(options);
+    app
+}
+
+fn headless_app(options: RunOptions, config: SimConfig, logging: bool) -> App {
+    let mut app = App::new();
+    app.insert_resource(SimRng::new(config.seed))
+        .insert_resource(config)
+        .add_plugins(HeadlessSystemPlugin { logging })
+        .add_plugins(GameBoardPlugin)
+        .add_plugins(PlayerActionPlugin)
+        .add_plugins(ReportPlugin)
+        .add_plugins(PersistencePlugin)
+        .add_plugins(LineagePlugin)
+        .add_plugins(ScentPlugin)
+        .insert_resource(options);
+    app
+}
+
+/// Everything a headless run passes on to the visualizer when it's asked to show the last generation.
+struct Handover {
+    genomes: Vec<Genome>,
+    rng: SimRng,
+    stats_log: StatsLog,
+    species: SpeciesRegistry,
+    mutation_rate: MutationRate,
+    hall_of_fame: HallOfFame,
+    lineage: LineageStore,
+}
+
+impl Handover {
+    /// The headless app stops with the next generation already bred and on the board,
+    /// so its genomes are exactly the generation that should be shown.
+    /// The random number generator, the stats, the NEAT species, the mutation rate, the hall of fame and the lineage
+    /// carry on from where the headless run left off. The genomes are born again in the visualizer.
+    fn take(app: &mut App) -> Self {
+        let world = app.world_mut();
+        let mut genomes: Vec<Genome> = world.query::<&Genome>().iter(world).cloned().collect();
+        let mut lineage = world.remove_resource::<LineageStore>().unwrap();
+        lineage.forget_births(&mut genomes);
+        Self {
+            genomes,
+            rng: world.remove_resource::<SimRng>().unwrap(),
+            stats_log: world.remove_resource::<StatsLog>().unwrap(),
+            species: world.remove_resource::<SpeciesRegistry>().unwrap(),
+            mutation_rate: world.remove_resource::<MutationRate>().unwrap(),
+            hall_of_fame: world.remove_resource::<HallOfFame>().unwrap(),
+            lineage,
+        }
+    }
+}
+
+/// What an app that starts from the first generation begins with: a fresh stats log and lineage,
+/// the hall of fame where previous runs left it, and the `--load`ed genomes, as many as there are players.
+/// Whoever's left gets a random genome as usual.
+struct FirstGeneration {
+    stats_log: StatsLog,
+    lineage: LineageStore,
+    hall_of_fame: HallOfFame,
+    gene_pool: GenePool,
+}
+
+impl FirstGeneration {
+    /// Islands keep their stats and their hall of fame apart from each other's.
+    fn prepare(
+        options: &RunOptions,
+        config: &SimConfig,
+        island: Option<usize>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let output_dir = match (&options.output_dir, island) {
+            (Some(output_dir), Some(index)) => {
+                Some(output_dir.join(format!("island_{}", index + 1)))
+            }
+            (output_dir, _) => output_dir.clone(),
+        };
+        let stats_log = match &output_dir {
+            Some(output_dir) => StatsLog::create(output_dir.clone())?,
+            None => StatsLog::default(),
+        };
+        let hall_of_fame = match (&options.hall_of_fame_path, island) {
+            (None, _) => HallOfFame::default(),
+            (Some(path), Some(index)) => {
+                HallOfFame::open(island_path(path, index), config.hall_of_fame_size as usize)?
+            }
+            (Some(path), None) => {
+                HallOfFame::open(path.clone(), config.hall_of_fame_size as usize)?
+            }
+        };
+        let genomes = match &options.load_path {
+            None => vec![],
+            Some(path) => GenomeFile::load(path)?
+                .genomes
+                .iter()
+                .take(config.player_count() as usize)
+                .map(GenomeRecord::genome)
+                .collect(),
+        };
+        Ok(Self {
+            stats_log,
+            lineage: LineageStore::new(output_dir),
+            hall_of_fame,
+            gene_pool: GenePool { genomes },
+        })
+    }
+
+    fn insert_into(self, app: &mut App) {
+        app.insert_resource(self.stats_log)
+            .insert_resource(self.lineage)
+            .insert_resource(self.hall_of_fame)
+            .insert_resource(self.gene_pool);
+    }
+}
+
+/// Evolve `config.islands` populations side by side, each one headless on its own thread.
+/// If the last generation is to be shown, it's the one of the first island.
+fn run_islands(
+    options: RunOptions,
+    config: SimConfig,
+    show_last: bool,
+) -> (AppExit, Option<Handover>) {
+    let mut run_rng = SimRng::new(config.seed);
+    let islands = connect_islands(
+        config.islands as usize,
+        config.migration_topology,
+        run_rng.seed(),
+    );
+
+    let mut threads = vec![];
+    for (island, immigration) in islands {
+        let first_generation = match FirstGeneration::prepare(&options, &config, Some(island.index))
+        {
+            Ok(first_generation) => first_generation,
+            Err(e) => {
+                eprintln!("{}", e);
+                return (AppExit::error(), None);
+            }
+        };
+        let rng = run_rng.fork();
+        let (options, config) = (options.clone(), config.clone());
+        threads.push(thread::spawn(move || {
+            let first = island.index == 0;
+            let mut app = headless_app(options, config, first);
+            first_generation.insert_into(&mut app);
+            app.insert_resource(rng)
+                .insert_resource(island)
+                .insert_non_send_resource(immigration)
+                .add_plugins(IslandPlugin);
+            let exit = run_headless(&mut app);
+            let handover =
+                (first && show_last && exit.is_success()).then(|| Handover::take(&mut app));
+            (exit, handover)
+        }));
+    }
+
+    let mut outcome = (AppExit::Success, None);
+    for thread in threads {
+        match thread.join() {
+            Ok((exit, handover)) => {
+                if exit.is_error() {
+                    outcome.0 = exit;
+                }
+                outcome.1 = outcome.1.or(handover);
+            }
+            Err(_) => outcome.0 = AppExit::error(),
+        }
+    }
+    outcome
+}
+
+fn main() -> AppExit {
+    let options = match RunOptions::from_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, RunOptions::USAGE);
+            return AppExit::error();
+        }
+    };
+    let config = match options.sim_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return AppExit::error();
+        }
+    };
+    if config.islands > 1 && !options.headless {
+        eprintln!("Islands can only be simulated with --headless");
+        return AppExit::error();
+    }
+
+    let generations = options.generations;
+    // Showing only one generation doesn't need a headless run at all.
+    let headless_options = match (options.headless, options.show_last, generations) {
+        (false, _, _) | (true, true, Some(1)) => None,
+        (true, true, Some(limit)) => Some(RunOptions {
+            generations: Some(limit - 1),
+            ..options.clone()
+        }),
+        _ => Some(options.clone()),
+    };
+    let Some(headless_options) = headless_options else {
+        let mut visual = visual_app(options.clone(), config.clone());
+        match FirstGeneration::prepare(&options, &config, None) {
+            Ok(first_generation) => first_generation.insert_into(&mut visual),
+            Err(e) => {
+                eprintln!("{}", e);
+                return AppExit::error();
+            }
+        }
+        return visual.run();
+    };
+
+    let (exit, handover) = if config.islands > 1 {
+        run_islands(headless_options, config.clone(), options.show_last)
+    } else {
+        let mut headless = headless_app(headless_options, config.clone(), true);
+        match FirstGeneration::prepare(&options, &config, None) {
+            Ok(first_generation) => first_generation.insert_into(&mut headless),
+            Err(e) => {
+                eprintln!("{}", e);
+                return AppExit::error();
+            }
+        }
+        let exit = run_headless(&mut headless);
+        let handover =
+            (options.show_last && exit.is_success()).then(|| Handover::take(&mut headless));
+        (exit, handover)
+    };
+    let Some(handover) = handover else {
+        return exit;
+    };
+
+    let mut visual = visual_app(options, config);
+    visual
+        .insert_resource(GenePool {
+            genomes: handover.genomes,
+        })
+        .insert_resource(handover.rng)
+        .insert_resource(handover.stats_log)
+        .insert_resource(handover.species)
+        .insert_resource(handover.mutation_rate)
+        .insert_resource(handover.hall_of_fame)
+        .insert_resource(handover.lineage)
+        .insert_resource(Generation {
+            num: generations.unwrap_or(1),
+        });
+    visual.run()
 }
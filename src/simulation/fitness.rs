@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::players::PlayerStats;
+
+/// How good a player did during its generation. Players with higher scores make better parents.
+pub trait FitnessFunction {
+    fn score(&self, stats: &PlayerStats) -> f32;
+}
+
+pub struct TurnsSurvived;
+
+impl FitnessFunction for TurnsSurvived {
+    fn score(&self, stats: &PlayerStats) -> f32 {
+        stats.turns_survived as f32
+    }
+}
+
+/// The energy the player was left with at the end of the generation, 0 if it died.
+pub struct FinalEnergy;
+
+impl FitnessFunction for FinalEnergy {
+    fn score(&self, stats: &PlayerStats) -> f32 {
+        stats.energy as f32
+    }
+}
+
+pub struct FoodEaten;
+
+impl FitnessFunction for FoodEaten {
+    fn score(&self, stats: &PlayerStats) -> f32 {
+        stats.food_eaten as f32
+    }
+}
+
+pub struct Kills;
+
+impl FitnessFunction for Kills {
+    fn score(&self, stats: &PlayerStats) -> f32 {
+        stats.kills as f32
+    }
+}
+
+pub struct WallsBuilt;
+
+impl FitnessFunction for WallsBuilt {
+    fn score(&self, stats: &PlayerStats) -> f32 {
+        stats.walls_built as f32
+    }
+}
+
+/// The weighted sum of other fitness functions.
+pub struct Weighted {
+    pub terms: Vec<(f32, Box<dyn FitnessFunction>)>,
+}
+
+impl FitnessFunction for Weighted {
+    fn score(&self, stats: &PlayerStats) -> f32 {
+        self.terms
+            .iter()
+            .map(|(weight, function)| weight * function.score(stats))
+            .sum()
+    }
+}
+
+/// How much each of the built-in fitness functions counts towards a player's fitness.
+/// Weights can be negative, e.g. to punish building walls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FitnessWeights {
+    pub turns_survived: f32,
+    pub energy: f32,
+    pub food_eaten: f32,
+    pub kills: f32,
+    pub walls_built: f32,
+}
+
+impl Default for FitnessWeights {
+    fn default() -> Self {
+        Self {
+            turns_survived: 1.0,
+            energy: 1.0,
+            food_eaten: 0.0,
+            kills: 0.0,
+            walls_built: 0.0,
+        }
+    }
+}
+
+impl FitnessWeights {
+    pub fn function(&self) -> Box<dyn FitnessFunction> {
+        let terms: Vec<(f32, Box<dyn FitnessFunction>)> = vec![
+            (self.turns_survived, Box::new(TurnsSurvived)),
+            (self.energy, Box::new(FinalEnergy)),
+            (self.food_eaten, Box::new(FoodEaten)),
+            (self.kills, Box::new(Kills)),
+            (self.walls_built, Box::new(WallsBuilt)),
+        ];
+        Box::new(Weighted {
+            terms: terms
+                .into_iter()
+                .filter(|(weight, _)| *weight != 0.0)
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,175 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::common::{rerror, StableHasher};
+use crate::engine::config::*;
+use crate::engine::random::random_player_action;
+use crate::simulation::genome::*;
+use crate::simulation::players::*;
+
+/// What a state machine reacts to: the closest thing within the line of sight (what a scan would report)
+/// and how hungry the player is.
+const SYMBOLS: usize = TileKind::COUNT * ENERGY_BUCKETS;
+const TILE_KINDS: [TileKind; TileKind::COUNT] = [
+    TileKind::Empty,
+    TileKind::Player,
+    TileKind::Food,
+    TileKind::Wall,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Transition {
+    pub action: PlayerActionType,
+    pub next_state: usize,
+}
+
+/// A finite state machine: in every one of its `FSM_STATES` states, every perception symbol maps to
+/// an action and the state to be in on the next turn. The current state is the player's memory across turns,
+/// and every player is born in state 0, clones included.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FsmBrain {
+    /// `SYMBOLS` transitions per state, state by state.
+    transitions: Vec<Transition>,
+    #[serde(skip)]
+    state: usize,
+}
+
+impl Clone for FsmBrain {
+    fn clone(&self) -> Self {
+        Self {
+            transitions: self.transitions.clone(),
+            state: 0,
+        }
+    }
+}
+
+impl FsmBrain {
+    pub fn random(rng: &mut dyn RngCore) -> Self {
+        Self {
+            transitions: (0..FSM_STATES * SYMBOLS)
+                .map(|_| random_transition(rng))
+                .collect(),
+            state: 0,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.transitions.len() != FSM_STATES * SYMBOLS {
+            return Err(rerror(&format!(
+                "a state machine has {} transitions, not {}",
+                self.transitions.len(),
+                FSM_STATES * SYMBOLS
+            )));
+        }
+        for transition in self.transitions.iter() {
+            if transition.next_state >= FSM_STATES {
+                return Err(rerror(&format!(
+                    "a state machine goes to state {}, but only has {} states",
+                    transition.next_state, FSM_STATES
+                )));
+            }
+            validate_action(transition.action)?;
+        }
+        Ok(())
+    }
+
+    fn symbol(situation: &Situation) -> usize {
+        let first_seen = situation
+            .first_seen()
+            .map_or(TileKind::Empty, |(_, kind)| kind);
+        first_seen.index() * ENERGY_BUCKETS + situation.energy_bucket
+    }
+}
+
+fn random_transition(rng: &mut dyn RngCore) -> Transition {
+    Transition {
+        action: random_player_action(rng),
+        next_state: rng.gen_range(0..FSM_STATES),
+    }
+}
+
+impl Brain for FsmBrain {
+    fn decide(&mut self, situation: &Situation, _rng: &mut dyn RngCore) -> PlayerActionType {
+        let transition = self.transitions[self.state * SYMBOLS + Self::symbol(situation)];
+        self.state = transition.next_state;
+        transition.action
+    }
+
+    /// Uniform crossover over the transitions. The child is born in state 0 like everybody else.
+    fn crossover(&self, other: &dyn Brain, rng: &mut dyn RngCore) -> Box<dyn Brain> {
+        let Some(other) = other.as_any().downcast_ref::<FsmBrain>() else {
+            return self.box_clone();
+        };
+        Box::new(FsmBrain {
+            transitions: self
+                .transitions
+                .iter()
+                .zip(other.transitions.iter())
+                .map(|(mine, theirs)| if rng.gen_bool(0.5) { *mine } else { *theirs })
+                .collect(),
+            state: 0,
+        })
+    }
+
+    /// Every transition has a `rate` chance of getting either a new action or a new next state.
+    fn mutate(&mut self, rate: f32, rng: &mut dyn RngCore) {
+        for transition in self.transitions.iter_mut() {
+            if rng.gen::<f32>() < rate {
+                let mutated = random_transition(rng);
+                match rng.gen_bool(0.5) {
+                    true => transition.action = mutated.action,
+                    false => transition.next_state = mutated.next_state,
+                }
+            }
+        }
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        self.transitions.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn distance(&self, other: &dyn Brain) -> f32 {
+        match other.as_any().downcast_ref::<FsmBrain>() {
+            Some(other) => differing_share(&self.transitions, &other.transitions),
+            None => 1.0,
+        }
+    }
+
+    fn to_data(&self) -> BrainData {
+        BrainData::Fsm(self.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn Brain> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The whole transition table, one line per state and perception, e.g. `0: Food/2 -> eat, 1`.
+impl fmt::Display for FsmBrain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, transition) in self.transitions.iter().enumerate() {
+            let (state, symbol) = (index / SYMBOLS, index % SYMBOLS);
+            writeln!(
+                f,
+                "{}: {:?}/{} -> {}, {}",
+                state,
+                TILE_KINDS[symbol / ENERGY_BUCKETS],
+                symbol % ENERGY_BUCKETS,
+                transition.action.name(),
+                transition.next_state
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,698 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::common::*;
+use crate::engine::config::*;
+use crate::engine::random::{random_gaussian, random_player_action};
+use crate::engine::scent::{ScentField, ScentLayer};
+use crate::simulation::fsm::FsmBrain;
+use crate::simulation::gp::GpBrain;
+use crate::simulation::neat::NeatBrain;
+use crate::simulation::nn::NnBrain;
+use crate::simulation::players::*;
+
+/// A coarse classification of a tile, as far as a player's senses are concerned.
+/// Everything beyond the edge of the board is perceived as a wall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TileKind {
+    Empty,
+    Player,
+    Food,
+    Wall,
+}
+
+impl TileKind {
+    pub const COUNT: usize = 4;
+
+    pub fn index(&self) -> usize {
+        match self {
+            TileKind::Empty => 0,
+            TileKind::Player => 1,
+            TileKind::Food => 2,
+            TileKind::Wall => 3,
+        }
+    }
+}
+
+impl From<&OccupantType> for TileKind {
+    fn from(occ: &OccupantType) -> Self {
+        match occ {
+            OccupantType::Empty => TileKind::Empty,
+            OccupantType::Player(_) => TileKind::Player,
+            OccupantType::Food(_) => TileKind::Food,
+            OccupantType::Wall(_) => TileKind::Wall,
+        }
+    }
+}
+
+/// What a player remembers of something its last scan found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Memory {
+    pub kind: TileKind,
+    /// How many turns ago it was seen.
+    pub age: u32,
+    /// Where it was seen, relative to where the player is and faces now: how many tiles ahead
+    /// (negative if behind) and to the right (negative if to the left).
+    pub ahead: i32,
+    pub right: i32,
+}
+
+impl Memory {
+    /// What a player standing at `pos` and facing `facing` on turn `turn` makes of what its last scan found, closest first.
+    pub fn recall(
+        perception: &Perception,
+        pos: &BoardPosition,
+        facing: &FacingDirection,
+        turn: u32,
+    ) -> Vec<Self> {
+        perception
+            .in_sight
+            .iter()
+            .map(|sighting| Self::recall_sighting(sighting, pos, facing, turn))
+            .collect()
+    }
+
+    fn recall_sighting(
+        sighting: &Sighting,
+        pos: &BoardPosition,
+        facing: &FacingDirection,
+        turn: u32,
+    ) -> Self {
+        let (ahead, right) = relative_offset(pos, facing, &sighting.pos);
+        Self {
+            kind: TileKind::from(&sighting.seen),
+            age: turn.saturating_sub(sighting.turn),
+            ahead,
+            right,
+        }
+    }
+
+    /// Which way it mostly lies, relative to where the player faces (`Up` is ahead),
+    /// or nothing if it was seen right where the player is now.
+    pub fn direction(&self) -> Option<FacingDirection> {
+        relative_direction(self.ahead, self.right)
+    }
+}
+
+/// A tile within the player's line of sight and what's on it, relative to where the player is and faces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seen {
+    pub kind: TileKind,
+    pub ahead: i32,
+    pub right: i32,
+}
+
+impl Seen {
+    /// How far away it is, 0 being next to the player, diagonal steps included.
+    pub fn distance(&self) -> usize {
+        (self.ahead.unsigned_abs().max(self.right.unsigned_abs()) as usize).saturating_sub(1)
+    }
+
+    /// Which way it mostly lies, relative to where the player faces (`Up` is ahead).
+    pub fn direction(&self) -> FacingDirection {
+        relative_direction(self.ahead, self.right).unwrap_or(FacingDirection::Up)
+    }
+}
+
+/// How many tiles ahead (negative if behind) and to the right (negative if to the left) of a player
+/// standing at `pos` and facing `facing` the tile at `tile` is.
+fn relative_offset(
+    pos: &BoardPosition,
+    facing: &FacingDirection,
+    tile: &BoardPosition,
+) -> (i32, i32) {
+    let (dx, dy) = (tile.x as i32 - pos.x as i32, tile.y as i32 - pos.y as i32);
+    match facing {
+        FacingDirection::Up => (dy, dx),
+        FacingDirection::Right => (dx, -dy),
+        FacingDirection::Down => (-dy, -dx),
+        FacingDirection::Left => (-dx, dy),
+    }
+}
+
+/// Which way an offset mostly points (`Up` is ahead), or nothing for no offset at all.
+fn relative_direction(ahead: i32, right: i32) -> Option<FacingDirection> {
+    match (ahead, right) {
+        (0, 0) => None,
+        (ahead, right) if ahead.abs() >= right.abs() => match ahead > 0 {
+            true => Some(FacingDirection::Up),
+            false => Some(FacingDirection::Down),
+        },
+        (_, right) => match right > 0 {
+            true => Some(FacingDirection::Right),
+            false => Some(FacingDirection::Left),
+        },
+    }
+}
+
+/// Ahead, to the right, behind and to the left.
+pub const RELATIVE_DIRECTIONS: [FacingDirection; 4] = [
+    FacingDirection::Up,
+    FacingDirection::Right,
+    FacingDirection::Down,
+    FacingDirection::Left,
+];
+
+/// How strong a scent is where the player stands and on the four tiles around it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Smell {
+    pub here: f32,
+    /// Ahead, to the right, behind and to the left, 0 beyond the edge of the board.
+    pub around: [f32; 4],
+}
+
+impl Smell {
+    fn sniff(
+        scents: &ScentField,
+        layer: ScentLayer,
+        pos: &BoardPosition,
+        around: &[Option<BoardPosition>; 4],
+    ) -> Self {
+        Self {
+            here: scents.at(layer, pos),
+            around: around.map(|tile| tile.map_or(0.0, |tile| scents.at(layer, &tile))),
+        }
+    }
+
+    /// Which way the scent gets stronger, relative to where the player is facing (`Up` is ahead),
+    /// or nothing if it's nowhere around stronger than where the player stands.
+    pub fn strongest(&self) -> Option<FacingDirection> {
+        let (strongest, scent) = self
+            .around
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        (*scent > self.here).then_some(RELATIVE_DIRECTIONS[strongest])
+    }
+}
+
+/// Everything a player knows about its surroundings at the moment it has to pick its next action.
+#[derive(Debug, Clone)]
+pub struct Situation {
+    pub energy: u32,
+    /// How hungry the player is, from 0 (starving) to `ENERGY_BUCKETS - 1` (well fed).
+    pub energy_bucket: usize,
+    pub health: u32,
+    /// How much of its health the player has left, from 0 to 1.
+    pub health_share: f32,
+    pub facing: FacingDirection,
+    /// What the tiles within the player's line of sight contain and where they are,
+    /// in the order `get_los_tiles` gives them.
+    pub los: Vec<Seen>,
+    /// How many tiles away the player's line of sight reaches.
+    pub reach: usize,
+    /// What the four tiles around the player contain: ahead, to the right, behind and to the left.
+    pub adjacent: [TileKind; 4],
+    /// The closest thing the player's scans found last, if they ever found anything.
+    pub memory: Option<Memory>,
+    /// Everything the last scan that found anything found, closest first.
+    pub in_sight: Vec<Memory>,
+    pub food_scent: Smell,
+    pub pheromone: Smell,
+}
+
+impl Situation {
+    #[allow(clippy::too_many_arguments)]
+    pub fn observe(
+        pos: &BoardPosition,
+        facing: &FacingDirection,
+        los: &LineOfSight,
+        vitals: &Vitals,
+        in_sight: Vec<Memory>,
+        board: &Board,
+        scents: &ScentField,
+        config: &SimConfig,
+    ) -> Self {
+        let reach = los.length as usize;
+        let los = get_los_tiles(pos, facing, los, board)
+            .iter()
+            .map(|tile| {
+                let (ahead, right) = relative_offset(pos, facing, tile);
+                Seen {
+                    kind: board.occ_at(tile).map_or(TileKind::Wall, TileKind::from),
+                    ahead,
+                    right,
+                }
+            })
+            .collect();
+        let around = RELATIVE_DIRECTIONS.map(|relative| {
+            board
+                .looking_at(pos, &relative_to(facing, relative))
+                .map(|(tile, _)| tile)
+        });
+        let adjacent = around.map(|tile| {
+            tile.and_then(|tile| board.occ_at(&tile))
+                .map_or(TileKind::Wall, TileKind::from)
+        });
+
+        Self {
+            energy: vitals.energy.value,
+            energy_bucket: ((vitals.energy.value / config.energy_bucket_size().max(1)) as usize)
+                .min(ENERGY_BUCKETS - 1),
+            health: vitals.health.value,
+            health_share: vitals.health.value as f32 / vitals.health.max.max(1) as f32,
+            facing: *facing,
+            los,
+            reach,
+            adjacent,
+            memory: in_sight.first().copied(),
+            in_sight,
+            food_scent: Smell::sniff(scents, ScentLayer::Food, pos, &around),
+            pheromone: Smell::sniff(scents, ScentLayer::Pheromone, pos, &around),
+        }
+    }
+
+    /// What's on the tile next to the player in the given direction, relative to where the player is facing
+    /// (`Up` is ahead, `Down` is behind).
+    pub fn adjacent(&self, relative: FacingDirection) -> TileKind {
+        match relative {
+            FacingDirection::Up => self.adjacent[0],
+            FacingDirection::Right => self.adjacent[1],
+            FacingDirection::Down => self.adjacent[2],
+            FacingDirection::Left => self.adjacent[3],
+        }
+    }
+
+    /// What the player sees `distance` tiles away (0 is next to it) in the given direction, relative to where
+    /// it's facing (`Up` is ahead): the first thing there, or empty if there's nothing,
+    /// or nothing if no tile of its line of sight lies there.
+    pub fn seen_towards(&self, distance: usize, direction: FacingDirection) -> Option<TileKind> {
+        let mut kinds = self
+            .los
+            .iter()
+            .filter(|seen| seen.distance() == distance && seen.direction() == direction)
+            .map(|seen| seen.kind)
+            .peekable();
+        kinds.peek()?;
+        Some(
+            kinds
+                .find(|kind| *kind != TileKind::Empty)
+                .unwrap_or(TileKind::Empty),
+        )
+    }
+
+    /// The closest non-empty thing within the line of sight and the distance to it, if any.
+    pub fn first_seen(&self) -> Option<(usize, TileKind)> {
+        self.los
+            .iter()
+            .filter(|seen| seen.kind != TileKind::Empty)
+            .min_by_key(|seen| seen.distance())
+            .map(|seen| (seen.distance(), seen.kind))
+    }
+
+    /// How many things of the given kind the last scan that found anything found.
+    pub fn in_sight_count(&self, kind: TileKind) -> usize {
+        self.in_sight
+            .iter()
+            .filter(|memory| memory.kind == kind)
+            .count()
+    }
+}
+
+/// The absolute direction of `relative` (with `Up` meaning ahead) for a player facing `facing`.
+fn relative_to(facing: &FacingDirection, relative: FacingDirection) -> FacingDirection {
+    match relative {
+        FacingDirection::Up => *facing,
+        FacingDirection::Down => position_after_turn(
+            &position_after_turn(facing, FacingDirection::Right).unwrap(),
+            FacingDirection::Right,
+        )
+        .unwrap(),
+        turn => position_after_turn(facing, turn).unwrap(),
+    }
+}
+
+/// A decision-making strategy encoded by a player's genes.
+/// Different genome encodings implement this trait so they can be swapped in without touching the engine.
+pub trait Brain: Debug + Send + Sync {
+    fn decide(&mut self, situation: &Situation, rng: &mut dyn RngCore) -> PlayerActionType;
+
+    /// Produce a child brain from `self` and `other`.
+    /// Brains of different encodings can't be crossed, in which case the child is a copy of `self`.
+    fn crossover(&self, other: &dyn Brain, rng: &mut dyn RngCore) -> Box<dyn Brain>;
+
+    fn mutate(&mut self, rate: f32, rng: &mut dyn RngCore);
+
+    /// A hash of the brain's genes: brains with equal genes have equal fingerprints.
+    fn fingerprint(&self) -> u64;
+
+    /// How different the genes of `self` and `other` are, from 0 (identical) to 1 (nothing in common).
+    /// Brains of different encodings have nothing in common.
+    fn distance(&self, other: &dyn Brain) -> f32;
+
+    /// The brain's genes in a form that can be saved to disk and turned back into the same brain.
+    fn to_data(&self) -> BrainData;
+
+    fn box_clone(&self) -> Box<dyn Brain>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrainKind {
+    Random,
+    Reflex,
+    Gp,
+    Nn,
+    Neat,
+    Fsm,
+}
+
+/// Any brain, by value, tagged with its kind. What brains are saved to disk as.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum BrainData {
+    Random,
+    Reflex(ReflexBrain),
+    Gp(GpBrain),
+    Nn(NnBrain),
+    Neat(NeatBrain),
+    Fsm(FsmBrain),
+}
+
+impl BrainData {
+    pub fn into_brain(self) -> Box<dyn Brain> {
+        match self {
+            BrainData::Random => Box::new(RandomBrain),
+            BrainData::Reflex(brain) => Box::new(brain),
+            BrainData::Gp(brain) => Box::new(brain),
+            BrainData::Nn(brain) => Box::new(brain),
+            BrainData::Neat(brain) => Box::new(brain),
+            BrainData::Fsm(brain) => Box::new(brain),
+        }
+    }
+
+    /// Whether the brain is one that could have evolved here, so that a hand-edited or damaged genome file
+    /// gets rejected instead of crashing the run once the brain is used.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            BrainData::Random => Ok(()),
+            BrainData::Reflex(brain) => brain.validate(),
+            BrainData::Gp(brain) => brain.validate(),
+            BrainData::Nn(brain) => brain.validate(),
+            BrainData::Neat(brain) => brain.validate(),
+            BrainData::Fsm(brain) => brain.validate(),
+        }
+    }
+}
+
+/// Players can only take the actions in `PlayerActionType::ALL`, e.g. there's no turning around.
+pub fn validate_action(action: PlayerActionType) -> Result<(), Box<dyn Error>> {
+    match PlayerActionType::ALL.contains(&action) {
+        true => Ok(()),
+        false => Err(rerror(&format!(
+            "{:?} isn't an action players can take",
+            action
+        ))),
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Genome {
+    pub brain: Box<dyn Brain>,
+    /// Given to the genome when a player is born with it, 0 until then.
+    pub id: u64,
+    /// The ids of the genomes this one was bred from. Empty for genomes born at random.
+    pub parents: Vec<u64>,
+    /// The shape of the player's line of sight, when it's evolved rather than configured.
+    pub los_shape: Option<LosShape>,
+    /// How hard the player hits, when damage scales with strength. Between `MIN_STRENGTH` and `MAX_STRENGTH`.
+    pub strength: Option<f32>,
+}
+
+impl Clone for Genome {
+    fn clone(&self) -> Self {
+        Self {
+            brain: self.brain.box_clone(),
+            id: self.id,
+            parents: self.parents.clone(),
+            los_shape: self.los_shape,
+            strength: self.strength,
+        }
+    }
+}
+
+impl Genome {
+    pub fn random(kind: BrainKind, rng: &mut dyn RngCore) -> Self {
+        let brain: Box<dyn Brain> = match kind {
+            BrainKind::Random => Box::new(RandomBrain),
+            BrainKind::Reflex => Box::new(ReflexBrain::random(rng)),
+            BrainKind::Gp => Box::new(GpBrain::random(rng)),
+            BrainKind::Nn => Box::new(NnBrain::random(rng)),
+            BrainKind::Neat => Box::new(NeatBrain::random(rng)),
+            BrainKind::Fsm => Box::new(FsmBrain::random(rng)),
+        };
+        Self::new(brain, vec![])
+    }
+
+    pub fn new(brain: Box<dyn Brain>, parents: Vec<u64>) -> Self {
+        Self {
+            brain,
+            id: 0,
+            parents,
+            los_shape: None,
+            strength: None,
+        }
+    }
+
+    /// A child of the two genomes with the given brain, inheriting the shape of its line of sight from either of them
+    /// and a strength halfway between theirs.
+    pub fn offspring(
+        brain: Box<dyn Brain>,
+        mother: &Genome,
+        father: &Genome,
+        rng: &mut dyn RngCore,
+    ) -> Genome {
+        let mut child = Genome::new(brain, vec![mother.id, father.id]);
+        child.los_shape = match (mother.los_shape, father.los_shape) {
+            (Some(mine), Some(theirs)) => Some(if rng.gen_bool(0.5) { mine } else { theirs }),
+            (mine, theirs) => mine.or(theirs),
+        };
+        child.strength = match (mother.strength, father.strength) {
+            (Some(mine), Some(theirs)) => Some((mine + theirs) / 2.0),
+            (mine, theirs) => mine.or(theirs),
+        };
+        child
+    }
+
+    /// Give the genome the id of the player being born with it. A genome that already has an id
+    /// is a copy of another player's, like an elite's or a migrant's, and that player becomes its only parent.
+    pub fn born(&mut self, id: u64) {
+        if self.id != 0 {
+            self.parents = vec![self.id];
+        }
+        self.id = id;
+    }
+
+    pub fn decide(&mut self, situation: &Situation, rng: &mut dyn RngCore) -> PlayerActionType {
+        self.brain.decide(situation, rng)
+    }
+
+    pub fn crossover(&self, other: &Genome, rng: &mut dyn RngCore) -> Genome {
+        let brain = self.brain.crossover(other.brain.as_ref(), rng);
+        Genome::offspring(brain, self, other, rng)
+    }
+
+    /// An evolving line of sight has a `rate` chance of changing into a random shape,
+    /// and an evolving strength has a `rate` chance of getting a little noise added.
+    pub fn mutate(&mut self, rate: f32, rng: &mut dyn RngCore) {
+        self.brain.mutate(rate, rng);
+        if let Some(los_shape) = self.los_shape.as_mut() {
+            if rng.gen::<f32>() < rate {
+                *los_shape = *LosShape::ALL.choose(rng).unwrap();
+            }
+        }
+        if let Some(strength) = self.strength.as_mut() {
+            if rng.gen::<f32>() < rate {
+                *strength = (*strength + random_gaussian(rng) * STRENGTH_MUTATION_STRENGTH)
+                    .clamp(MIN_STRENGTH, MAX_STRENGTH);
+            }
+        }
+    }
+
+    pub fn fingerprint(&self) -> u64 {
+        match (self.los_shape, self.strength) {
+            (None, None) => self.brain.fingerprint(),
+            (los_shape, strength) => {
+                let mut hasher = StableHasher::default();
+                (
+                    self.brain.fingerprint(),
+                    los_shape,
+                    strength.map(f32::to_bits),
+                )
+                    .hash(&mut hasher);
+                hasher.finish()
+            }
+        }
+    }
+
+    /// Genomes seeing different shapes are halfway apart already, however alike their brains are,
+    /// and a gap in strength takes them the same share of the range of strengths further apart.
+    pub fn distance(&self, other: &Genome) -> f32 {
+        let mut distance = self.brain.distance(other.brain.as_ref());
+        if self.los_shape != other.los_shape {
+            distance = (distance + 1.0) / 2.0;
+        }
+        if let (Some(mine), Some(theirs)) = (self.strength, other.strength) {
+            let gap = (mine - theirs).abs() / (MAX_STRENGTH - MIN_STRENGTH);
+            distance += (1.0 - distance) * gap;
+        }
+        distance
+    }
+}
+
+/// The share of positions the two sequences differ at, with any difference in length counting as differing positions.
+pub fn differing_share<T: PartialEq>(mine: &[T], theirs: &[T]) -> f32 {
+    let longest = mine.len().max(theirs.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    let matching = mine
+        .iter()
+        .zip(theirs.iter())
+        .filter(|(mine, theirs)| mine == theirs)
+        .count();
+    (longest - matching) as f32 / longest as f32
+}
+
+/// The brain every player had before genetics existed: ignores everything and acts at random.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RandomBrain;
+
+impl Brain for RandomBrain {
+    fn decide(&mut self, _situation: &Situation, rng: &mut dyn RngCore) -> PlayerActionType {
+        random_player_action(rng)
+    }
+
+    fn crossover(&self, _other: &dyn Brain, _rng: &mut dyn RngCore) -> Box<dyn Brain> {
+        Box::new(RandomBrain)
+    }
+
+    fn mutate(&mut self, _rate: f32, _rng: &mut dyn RngCore) {}
+
+    fn fingerprint(&self) -> u64 {
+        0
+    }
+
+    fn distance(&self, other: &dyn Brain) -> f32 {
+        match other.as_any().is::<RandomBrain>() {
+            true => 0.0,
+            false => 1.0,
+        }
+    }
+
+    fn to_data(&self) -> BrainData {
+        BrainData::Random
+    }
+
+    fn box_clone(&self) -> Box<dyn Brain> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+const REFLEX_TABLE_SIZE: usize = TileKind::COUNT * TileKind::COUNT * ENERGY_BUCKETS;
+
+/// A stateless lookup table: one action for every combination of
+/// what's directly ahead, the closest thing within the line of sight and how hungry the player is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReflexBrain {
+    table: Vec<PlayerActionType>,
+}
+
+impl ReflexBrain {
+    pub fn random(rng: &mut dyn RngCore) -> Self {
+        Self {
+            table: (0..REFLEX_TABLE_SIZE)
+                .map(|_| random_player_action(rng))
+                .collect(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.table.len() != REFLEX_TABLE_SIZE {
+            return Err(rerror(&format!(
+                "a reflex table has {} entries, not {}",
+                self.table.len(),
+                REFLEX_TABLE_SIZE
+            )));
+        }
+        self.table
+            .iter()
+            .try_for_each(|action| validate_action(*action))
+    }
+
+    fn table_index(situation: &Situation) -> usize {
+        let ahead = situation.adjacent(FacingDirection::Up).index();
+        let first_seen = situation
+            .first_seen()
+            .map_or(TileKind::Empty, |(_, kind)| kind)
+            .index();
+
+        (ahead * TileKind::COUNT + first_seen) * ENERGY_BUCKETS + situation.energy_bucket
+    }
+}
+
+impl Brain for ReflexBrain {
+    fn decide(&mut self, situation: &Situation, _rng: &mut dyn RngCore) -> PlayerActionType {
+        self.table[Self::table_index(situation)]
+    }
+
+    fn crossover(&self, other: &dyn Brain, rng: &mut dyn RngCore) -> Box<dyn Brain> {
+        match other.as_any().downcast_ref::<ReflexBrain>() {
+            Some(other) => Box::new(ReflexBrain {
+                table: self
+                    .table
+                    .iter()
+                    .zip(other.table.iter())
+                    .map(|(mine, theirs)| if rng.gen_bool(0.5) { *mine } else { *theirs })
+                    .collect(),
+            }),
+            None => self.box_clone(),
+        }
+    }
+
+    fn mutate(&mut self, rate: f32, rng: &mut dyn RngCore) {
+        for action in self.table.iter_mut() {
+            if rng.gen::<f32>() < rate {
+                *action = random_player_action(rng);
+            }
+        }
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        self.table.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn distance(&self, other: &dyn Brain) -> f32 {
+        match other.as_any().downcast_ref::<ReflexBrain>() {
+            Some(other) => differing_share(&self.table, &other.table),
+            None => 1.0,
+        }
+    }
+
+    fn to_data(&self) -> BrainData {
+        BrainData::Reflex(self.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn Brain> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
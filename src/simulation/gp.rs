@@ -0,0 +1,460 @@
+use std::any::Any;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::common::{rerror, StableHasher};
+use crate::engine::config::*;
+use crate::engine::random::random_player_action;
+use crate::simulation::genome::*;
+use crate::simulation::players::*;
+
+/// The biggest number constant a program can be born with.
+const MAX_CONSTANT: u32 = 500;
+/// How likely a node is to be a terminal when a random tree is grown, as long as it isn't forced to be one.
+const TERMINAL_CHANCE: f64 = 0.3;
+
+const DIRECTIONS: [FacingDirection; 4] = [
+    FacingDirection::Up,
+    FacingDirection::Right,
+    FacingDirection::Down,
+    FacingDirection::Left,
+];
+const TILE_KINDS: [TileKind; TileKind::COUNT] = [
+    TileKind::Empty,
+    TileKind::Player,
+    TileKind::Food,
+    TileKind::Wall,
+];
+
+/// What a node evaluates to. Functions only ever get arguments of the types they expect,
+/// so every tree that gets grown, crossed over or mutated is a valid program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeType {
+    Action,
+    Bool,
+    Number,
+    Tile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Op {
+    // ACTION
+    Do(PlayerActionType),
+    /// `(Bool, Action, Action) -> Action`
+    If,
+
+    // BOOL
+    And,
+    Or,
+    Not,
+    /// `(Tile, Tile) -> Bool`
+    TileIs,
+    /// `(Number, Number) -> Bool`
+    LessThan,
+    /// Whether the player is facing the given direction.
+    Facing(FacingDirection),
+    /// Whether what the player remembers lies mostly in the given direction, relative to where it's facing (`Up` is ahead).
+    RememberedTowards(FacingDirection),
+    /// Whether food smells strongest in the given direction, relative to where the player's facing.
+    SmellsFoodTowards(FacingDirection),
+    /// Whether pheromones smell strongest in the given direction, relative to where the player's facing.
+    SmellsPheromoneTowards(FacingDirection),
+
+    // NUMBER
+    Energy,
+    Health,
+    /// How far away the closest thing within the line of sight is, or the line of sight's length if there's nothing.
+    DistanceToFirstSeen,
+    Const(u32),
+    /// How many turns ago the player saw what it remembers, or `u32::MAX` if it never saw anything.
+    MemoryAge,
+    /// How many things of the given kind the player's last scan found.
+    InSight(TileKind),
+
+    // TILE
+    /// The closest thing within the line of sight, or `Empty` if there's nothing.
+    FirstSeen,
+    /// The tile next to the player, relative to where it's facing (`Up` is ahead).
+    Adjacent(FacingDirection),
+    Tile(TileKind),
+    /// The last thing the player's scans found, or `Empty` if they never found anything.
+    Remembered,
+}
+
+impl Op {
+    fn output_type(&self) -> NodeType {
+        match self {
+            Op::Do(_) | Op::If => NodeType::Action,
+            Op::And
+            | Op::Or
+            | Op::Not
+            | Op::TileIs
+            | Op::LessThan
+            | Op::Facing(_)
+            | Op::RememberedTowards(_)
+            | Op::SmellsFoodTowards(_)
+            | Op::SmellsPheromoneTowards(_) => NodeType::Bool,
+            Op::Energy
+            | Op::Health
+            | Op::DistanceToFirstSeen
+            | Op::Const(_)
+            | Op::MemoryAge
+            | Op::InSight(_) => NodeType::Number,
+            Op::FirstSeen | Op::Adjacent(_) | Op::Tile(_) | Op::Remembered => NodeType::Tile,
+        }
+    }
+
+    fn arg_types(&self) -> &'static [NodeType] {
+        match self {
+            Op::If => &[NodeType::Bool, NodeType::Action, NodeType::Action],
+            Op::And | Op::Or => &[NodeType::Bool, NodeType::Bool],
+            Op::Not => &[NodeType::Bool],
+            Op::TileIs => &[NodeType::Tile, NodeType::Tile],
+            Op::LessThan => &[NodeType::Number, NodeType::Number],
+            _ => &[],
+        }
+    }
+
+    fn random_terminal(node_type: NodeType, rng: &mut dyn RngCore) -> Op {
+        match node_type {
+            NodeType::Action => Op::Do(random_player_action(rng)),
+            NodeType::Bool => {
+                let direction = *DIRECTIONS.choose(rng).unwrap();
+                match rng.gen_range(0..4) {
+                    0 => Op::Facing(direction),
+                    1 => Op::RememberedTowards(direction),
+                    2 => Op::SmellsFoodTowards(direction),
+                    _ => Op::SmellsPheromoneTowards(direction),
+                }
+            }
+            NodeType::Number => match rng.gen_range(0..6) {
+                0 => Op::Energy,
+                1 => Op::Health,
+                2 => Op::DistanceToFirstSeen,
+                3 => Op::MemoryAge,
+                4 => Op::InSight(*TILE_KINDS[1..].choose(rng).unwrap()),
+                _ => Op::Const(rng.gen_range(0..=MAX_CONSTANT)),
+            },
+            NodeType::Tile => match rng.gen_range(0..4) {
+                0 => Op::FirstSeen,
+                1 => Op::Adjacent(*DIRECTIONS.choose(rng).unwrap()),
+                2 => Op::Remembered,
+                _ => Op::Tile(*TILE_KINDS.choose(rng).unwrap()),
+            },
+        }
+    }
+
+    /// A random function returning `node_type`, if there are any.
+    fn random_function(node_type: NodeType, rng: &mut dyn RngCore) -> Option<Op> {
+        match node_type {
+            NodeType::Action => Some(Op::If),
+            NodeType::Bool => [Op::And, Op::Or, Op::Not, Op::TileIs, Op::LessThan]
+                .choose(rng)
+                .copied(),
+            NodeType::Number | NodeType::Tile => None,
+        }
+    }
+
+    /// A random op that can take this one's place without touching its arguments.
+    fn random_replacement(&self, rng: &mut dyn RngCore) -> Op {
+        match self {
+            Op::And | Op::Or => *[Op::And, Op::Or].choose(rng).unwrap(),
+            op if op.arg_types().is_empty() => Op::random_terminal(op.output_type(), rng),
+            op => *op,
+        }
+    }
+}
+
+/// A node of a program tree, along with its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Node {
+    op: Op,
+    args: Vec<Node>,
+}
+
+impl Node {
+    /// Whether the tree returns `node_type` and every function in it gets the arguments it expects.
+    fn validate(&self, node_type: NodeType) -> Result<(), Box<dyn Error>> {
+        if self.op.output_type() != node_type {
+            return Err(rerror(&format!(
+                "{:?} doesn't return a {:?}",
+                self.op, node_type
+            )));
+        }
+        if let Op::Do(action) = self.op {
+            validate_action(action)?;
+        }
+        let arg_types = self.op.arg_types();
+        if self.args.len() != arg_types.len() {
+            return Err(rerror(&format!(
+                "{:?} takes {} arguments, not {}",
+                self.op,
+                arg_types.len(),
+                self.args.len()
+            )));
+        }
+        self.args
+            .iter()
+            .zip(arg_types)
+            .try_for_each(|(arg, arg_type)| arg.validate(*arg_type))
+    }
+
+    /// Grow a random tree returning `node_type` that's at most `max_depth` nodes deep.
+    fn random(node_type: NodeType, max_depth: usize, rng: &mut dyn RngCore) -> Self {
+        let function = if max_depth > 1 && !rng.gen_bool(TERMINAL_CHANCE) {
+            Op::random_function(node_type, rng)
+        } else {
+            None
+        };
+        match function {
+            Some(op) => Self {
+                op,
+                args: op
+                    .arg_types()
+                    .iter()
+                    .map(|arg_type| Self::random(*arg_type, max_depth - 1, rng))
+                    .collect(),
+            },
+            None => Self {
+                op: Op::random_terminal(node_type, rng),
+                args: vec![],
+            },
+        }
+    }
+
+    fn size(&self) -> usize {
+        1 + self.args.iter().map(Node::size).sum::<usize>()
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.args.iter().map(Node::depth).max().unwrap_or(0)
+    }
+
+    /// Where every node of the tree is, in pre-order: how deep it sits (the root is at 1),
+    /// what type it returns and how deep its own subtree goes.
+    fn layout(&self) -> Vec<(usize, NodeType, usize)> {
+        let mut layout = vec![];
+        self.collect_layout(1, &mut layout);
+        layout
+    }
+
+    /// The ops of the whole tree, in pre-order.
+    fn ops(&self) -> Vec<Op> {
+        let mut ops = vec![self.op];
+        for arg in self.args.iter() {
+            ops.extend(arg.ops());
+        }
+        ops
+    }
+
+    fn collect_layout(&self, depth: usize, layout: &mut Vec<(usize, NodeType, usize)>) {
+        layout.push((depth, self.op.output_type(), self.depth()));
+        for arg in self.args.iter() {
+            arg.collect_layout(depth + 1, layout);
+        }
+    }
+
+    /// The node at `index` in pre-order.
+    fn get(&self, mut index: usize) -> &Node {
+        let mut node = self;
+        while index > 0 {
+            index -= 1;
+            let mut arg = 0;
+            while index >= node.args[arg].size() {
+                index -= node.args[arg].size();
+                arg += 1;
+            }
+            node = &node.args[arg];
+        }
+        node
+    }
+
+    fn get_mut(&mut self, mut index: usize) -> &mut Node {
+        let mut node = self;
+        while index > 0 {
+            index -= 1;
+            let mut arg = 0;
+            while index >= node.args[arg].size() {
+                index -= node.args[arg].size();
+                arg += 1;
+            }
+            node = &mut node.args[arg];
+        }
+        node
+    }
+
+    fn eval_action(&self, situation: &Situation) -> PlayerActionType {
+        match self.op {
+            Op::Do(action) => action,
+            Op::If => match self.args[0].eval_bool(situation) {
+                true => self.args[1].eval_action(situation),
+                false => self.args[2].eval_action(situation),
+            },
+            op => unreachable!("{:?} doesn't return an action", op),
+        }
+    }
+
+    fn eval_bool(&self, situation: &Situation) -> bool {
+        match self.op {
+            Op::And => self.args[0].eval_bool(situation) && self.args[1].eval_bool(situation),
+            Op::Or => self.args[0].eval_bool(situation) || self.args[1].eval_bool(situation),
+            Op::Not => !self.args[0].eval_bool(situation),
+            Op::TileIs => self.args[0].eval_tile(situation) == self.args[1].eval_tile(situation),
+            Op::LessThan => {
+                self.args[0].eval_number(situation) < self.args[1].eval_number(situation)
+            }
+            Op::Facing(direction) => situation.facing == direction,
+            Op::RememberedTowards(relative) => situation
+                .memory
+                .is_some_and(|memory| memory.direction() == Some(relative)),
+            Op::SmellsFoodTowards(relative) => situation.food_scent.strongest() == Some(relative),
+            Op::SmellsPheromoneTowards(relative) => {
+                situation.pheromone.strongest() == Some(relative)
+            }
+            op => unreachable!("{:?} doesn't return a bool", op),
+        }
+    }
+
+    fn eval_number(&self, situation: &Situation) -> u32 {
+        match self.op {
+            Op::Energy => situation.energy,
+            Op::Health => situation.health,
+            Op::DistanceToFirstSeen => situation
+                .first_seen()
+                .map_or(situation.reach, |(distance, _)| distance)
+                as u32,
+            Op::Const(value) => value,
+            Op::MemoryAge => situation.memory.map_or(u32::MAX, |memory| memory.age),
+            Op::InSight(kind) => situation.in_sight_count(kind) as u32,
+            op => unreachable!("{:?} doesn't return a number", op),
+        }
+    }
+
+    fn eval_tile(&self, situation: &Situation) -> TileKind {
+        match self.op {
+            Op::FirstSeen => situation
+                .first_seen()
+                .map_or(TileKind::Empty, |(_, kind)| kind),
+            Op::Adjacent(relative) => situation.adjacent(relative),
+            Op::Tile(kind) => kind,
+            Op::Remembered => situation
+                .memory
+                .map_or(TileKind::Empty, |memory| memory.kind),
+            op => unreachable!("{:?} doesn't return a tile", op),
+        }
+    }
+}
+
+/// A program evolved by genetic programming: a typed expression tree
+/// that looks at the player's senses and evaluates to the action to take.
+/// Trees never grow deeper than `GP_MAX_DEPTH`, which keeps bloat in check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpBrain {
+    program: Node,
+}
+
+impl GpBrain {
+    pub fn random(rng: &mut dyn RngCore) -> Self {
+        Self {
+            program: Node::random(NodeType::Action, GP_INITIAL_DEPTH, rng),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        self.program.validate(NodeType::Action)?;
+        let depth = self.program.depth();
+        if depth > GP_MAX_DEPTH {
+            return Err(rerror(&format!(
+                "the program is {} nodes deep, deeper than the {} allowed",
+                depth, GP_MAX_DEPTH
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Brain for GpBrain {
+    fn decide(&mut self, situation: &Situation, _rng: &mut dyn RngCore) -> PlayerActionType {
+        self.program.eval_action(situation)
+    }
+
+    /// Subtree crossover: a random subtree of `self` is replaced with a subtree of `other` of the same type,
+    /// as long as the child doesn't end up too deep.
+    fn crossover(&self, other: &dyn Brain, rng: &mut dyn RngCore) -> Box<dyn Brain> {
+        let Some(other) = other.as_any().downcast_ref::<GpBrain>() else {
+            return self.box_clone();
+        };
+        let mut child = self.clone();
+        let layout = child.program.layout();
+        let index = rng.gen_range(0..layout.len());
+        let (depth, node_type, _) = layout[index];
+
+        let candidates: Vec<usize> = other
+            .program
+            .layout()
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, other_type, other_depth))| {
+                *other_type == node_type && *other_depth <= (GP_MAX_DEPTH + 1).saturating_sub(depth)
+            })
+            .map(|(other_index, _)| other_index)
+            .collect();
+        if let Some(other_index) = candidates.choose(rng) {
+            *child.program.get_mut(index) = other.program.get(*other_index).clone();
+        }
+        Box::new(child)
+    }
+
+    /// Every node has a `rate` chance of a mutation, which is either a point mutation
+    /// (the node's op is swapped for a compatible one) or a subtree mutation (the node is regrown from scratch).
+    fn mutate(&mut self, rate: f32, rng: &mut dyn RngCore) {
+        let mutations = (0..self.program.size())
+            .filter(|_| rng.gen::<f32>() < rate)
+            .count();
+        for _ in 0..mutations {
+            let layout = self.program.layout();
+            let index = rng.gen_range(0..layout.len());
+            let (depth, node_type, _) = layout[index];
+            let node = self.program.get_mut(index);
+            if rng.gen_bool(0.5) {
+                node.op = node.op.random_replacement(rng);
+            } else {
+                let max_depth = (GP_MAX_DEPTH + 1)
+                    .saturating_sub(depth)
+                    .min(GP_INITIAL_DEPTH);
+                *node = Node::random(node_type, max_depth, rng);
+            }
+        }
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        self.program.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Compares the two programs op by op, in pre-order. It's cheap, and trees that share
+    /// their top nodes come out closer than ones that only share a subtree somewhere else.
+    fn distance(&self, other: &dyn Brain) -> f32 {
+        match other.as_any().downcast_ref::<GpBrain>() {
+            Some(other) => differing_share(&self.program.ops(), &other.program.ops()),
+            None => 1.0,
+        }
+    }
+
+    fn to_data(&self) -> BrainData {
+        BrainData::Gp(self.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn Brain> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1 +1,8 @@
+pub mod fitness;
+pub mod fsm;
+pub mod genome;
+pub mod gp;
+pub mod neat;
+pub mod nn;
 pub mod players;
+pub mod selection;
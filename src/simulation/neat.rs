@@ -0,0 +1,579 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+
+use bevy::prelude::*;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::common::{rerror, StableHasher};
+use crate::engine::config::*;
+use crate::engine::random::random_gaussian;
+use crate::simulation::genome::*;
+use crate::simulation::nn::{inputs, INPUTS, OUTPUTS};
+use crate::simulation::players::*;
+use crate::simulation::selection::SelectionStrategy;
+
+/// Every node that has to be activated, in order, along with its incoming connections (source node, weight).
+type ActivationPlan = Vec<(usize, Vec<(usize, f32)>)>;
+
+/// How many times adding a connection is attempted before giving up on the mutation.
+const ADD_CONNECTION_ATTEMPTS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeKind {
+    Input,
+    Output,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NodeGene {
+    pub id: u64,
+    pub kind: NodeKind,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ConnectionGene {
+    pub innovation: u64,
+    pub from: u64,
+    pub to: u64,
+    pub weight: f32,
+    pub enabled: bool,
+}
+
+/// The innovation number of a connection between two nodes.
+/// Innovation numbers are derived from the structure they describe rather than handed out by a global counter:
+/// the same connection gets the same number in every genome, no matter when or where it first appeared,
+/// and runs stay reproducible from their seed.
+fn innovation(from: u64, to: u64) -> u64 {
+    let mut hasher = StableHasher::default();
+    hasher.write_u64(from);
+    hasher.write_u64(to);
+    hasher.finish()
+}
+
+/// The id of the hidden node that splits the connection with the given innovation number.
+/// The top bit is always set, so hidden node ids never clash with input and output ids.
+fn split_node_id(split: u64) -> u64 {
+    let mut hasher = StableHasher::default();
+    hasher.write(b"node");
+    hasher.write_u64(split);
+    hasher.finish() | 1 << 63
+}
+
+/// A NEAT network: both the weights and the topology evolve, starting from every input connected to every output.
+/// Only feed-forward connections are ever added. Nodes use `tanh` and the strongest output picks the action,
+/// with the same inputs and outputs as `NnBrain`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeatBrain {
+    /// Inputs first and outputs next, in the order of their ids, then hidden nodes.
+    nodes: Vec<NodeGene>,
+    connections: Vec<ConnectionGene>,
+    /// Worked out the first time the network is used after it changes.
+    #[serde(skip)]
+    plan: Option<ActivationPlan>,
+}
+
+impl NeatBrain {
+    pub fn random(rng: &mut dyn RngCore) -> Self {
+        let nodes = (0..INPUTS as u64)
+            .map(|id| NodeGene {
+                id,
+                kind: NodeKind::Input,
+            })
+            .chain(
+                (INPUTS as u64..(INPUTS + OUTPUTS) as u64).map(|id| NodeGene {
+                    id,
+                    kind: NodeKind::Output,
+                }),
+            )
+            .collect();
+        let mut connections = vec![];
+        for from in 0..INPUTS as u64 {
+            for to in INPUTS as u64..(INPUTS + OUTPUTS) as u64 {
+                connections.push(ConnectionGene {
+                    innovation: innovation(from, to),
+                    from,
+                    to,
+                    weight: random_gaussian(rng),
+                    enabled: true,
+                });
+            }
+        }
+        Self {
+            nodes,
+            connections,
+            plan: None,
+        }
+    }
+
+    /// How different two genomes are: how many connection genes they don't have in common,
+    /// plus how far apart the weights of the ones they do have in common are on average.
+    /// As innovation numbers aren't ordered, excess and disjoint genes aren't told apart. The gene count
+    /// isn't normalized by genome size, as every genome starts fully connected and would drown out any new structure.
+    pub fn compatibility(&self, other: &NeatBrain) -> f32 {
+        let theirs: HashMap<u64, f32> = other
+            .connections
+            .iter()
+            .map(|gene| (gene.innovation, gene.weight))
+            .collect();
+        let (matching, weight_difference) = self
+            .connections
+            .iter()
+            .filter_map(|gene| {
+                theirs
+                    .get(&gene.innovation)
+                    .map(|weight| (gene.weight - weight).abs())
+            })
+            .fold((0, 0.0), |(count, sum), difference| {
+                (count + 1, sum + difference)
+            });
+        let not_matching = self.connections.len() + other.connections.len() - 2 * matching;
+
+        NEAT_DISJOINT_COEFFICIENT * not_matching as f32
+            + NEAT_WEIGHT_COEFFICIENT * weight_difference / matching.max(1) as f32
+    }
+
+    /// Inputs and outputs have to be where `random` puts them, and connections have to link existing nodes
+    /// without ever going back into an input, out of an output or round in a cycle.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let fixed = (0..(INPUTS + OUTPUTS) as u64).map(|id| match id < INPUTS as u64 {
+            true => (id, NodeKind::Input),
+            false => (id, NodeKind::Output),
+        });
+        if self.nodes.len() < INPUTS + OUTPUTS
+            || fixed
+                .zip(self.nodes.iter())
+                .any(|((id, kind), node)| node.id != id || node.kind != kind)
+        {
+            return Err(rerror(&format!(
+                "a NEAT network has to start with its {} inputs and {} outputs",
+                INPUTS, OUTPUTS
+            )));
+        }
+        let mut kinds: HashMap<u64, NodeKind> = HashMap::new();
+        for node in self.nodes.iter() {
+            if kinds.insert(node.id, node.kind).is_some() {
+                return Err(rerror(&format!(
+                    "a NEAT network has node {} twice",
+                    node.id
+                )));
+            }
+        }
+        if self.nodes[INPUTS + OUTPUTS..]
+            .iter()
+            .any(|node| node.kind != NodeKind::Hidden)
+        {
+            return Err(rerror(
+                "a NEAT network has inputs or outputs after its hidden nodes",
+            ));
+        }
+        let mut connected = HashSet::new();
+        for gene in self.connections.iter() {
+            match (kinds.get(&gene.from), kinds.get(&gene.to)) {
+                (None, _) | (_, None) => {
+                    return Err(rerror(&format!(
+                        "a NEAT network connects node {} to node {}, but doesn't have both",
+                        gene.from, gene.to
+                    )))
+                }
+                (Some(NodeKind::Output), _) | (_, Some(NodeKind::Input)) => {
+                    return Err(rerror(&format!(
+                        "a NEAT network connects node {} backwards to node {}",
+                        gene.from, gene.to
+                    )))
+                }
+                _ => (),
+            }
+            if !connected.insert((gene.from, gene.to)) {
+                return Err(rerror(&format!(
+                    "a NEAT network connects node {} to node {} twice",
+                    gene.from, gene.to
+                )));
+            }
+        }
+        match self
+            .connections
+            .iter()
+            .any(|gene| self.reaches(gene.to, gene.from))
+        {
+            true => Err(rerror("a NEAT network has a cycle")),
+            false => Ok(()),
+        }
+    }
+
+    fn has_node(&self, id: u64) -> bool {
+        self.nodes.iter().any(|node| node.id == id)
+    }
+
+    /// Whether `target` can be reached from `start` by following connections, disabled ones included.
+    fn reaches(&self, start: u64, target: u64) -> bool {
+        let mut stack = vec![start];
+        let mut visited = vec![];
+        while let Some(node) = stack.pop() {
+            if node == target {
+                return true;
+            }
+            if visited.contains(&node) {
+                continue;
+            }
+            visited.push(node);
+            stack.extend(
+                self.connections
+                    .iter()
+                    .filter(|gene| gene.from == node)
+                    .map(|gene| gene.to),
+            );
+        }
+        false
+    }
+
+    /// Connect two nodes that aren't connected yet, as long as that doesn't create a cycle.
+    fn add_connection(&mut self, rng: &mut dyn RngCore) {
+        for _ in 0..ADD_CONNECTION_ATTEMPTS {
+            let from = self.nodes[rng.gen_range(0..self.nodes.len())];
+            let to = self.nodes[rng.gen_range(0..self.nodes.len())];
+            if from.kind == NodeKind::Output
+                || to.kind == NodeKind::Input
+                || from.id == to.id
+                || self
+                    .connections
+                    .iter()
+                    .any(|gene| gene.from == from.id && gene.to == to.id)
+                || self.reaches(to.id, from.id)
+            {
+                continue;
+            }
+            self.connections.push(ConnectionGene {
+                innovation: innovation(from.id, to.id),
+                from: from.id,
+                to: to.id,
+                weight: random_gaussian(rng),
+                enabled: true,
+            });
+            return;
+        }
+    }
+
+    /// Split an enabled connection in two with a new hidden node. The connection into the node has a weight of 1
+    /// and the one out of it keeps the old weight, so the network behaves about the same as before.
+    fn add_node(&mut self, rng: &mut dyn RngCore) {
+        let enabled: Vec<usize> = (0..self.connections.len())
+            .filter(|index| self.connections[*index].enabled)
+            .collect();
+        if enabled.is_empty() {
+            return;
+        }
+        let split = enabled[rng.gen_range(0..enabled.len())];
+        let ConnectionGene {
+            innovation: split_innovation,
+            from,
+            to,
+            weight,
+            ..
+        } = self.connections[split];
+        let id = split_node_id(split_innovation);
+        if self.has_node(id) {
+            return;
+        }
+
+        self.connections[split].enabled = false;
+        self.nodes.push(NodeGene {
+            id,
+            kind: NodeKind::Hidden,
+        });
+        self.connections.push(ConnectionGene {
+            innovation: innovation(from, id),
+            from,
+            to: id,
+            weight: 1.0,
+            enabled: true,
+        });
+        self.connections.push(ConnectionGene {
+            innovation: innovation(id, to),
+            from: id,
+            to,
+            weight,
+            enabled: true,
+        });
+    }
+
+    fn compile(&self) -> ActivationPlan {
+        let index_of: HashMap<u64, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.id, index))
+            .collect();
+        let mut incoming = vec![vec![]; self.nodes.len()];
+        for gene in self.connections.iter().filter(|gene| gene.enabled) {
+            incoming[index_of[&gene.to]].push((index_of[&gene.from], gene.weight));
+        }
+
+        // Depth-first, so every node comes after all the nodes feeding into it.
+        let mut plan = vec![];
+        let mut planned = vec![false; self.nodes.len()];
+        for output in INPUTS..INPUTS + OUTPUTS {
+            let mut stack = vec![(output, false)];
+            while let Some((node, inputs_planned)) = stack.pop() {
+                if planned[node] || self.nodes[node].kind == NodeKind::Input {
+                    continue;
+                }
+                if inputs_planned {
+                    planned[node] = true;
+                    plan.push((node, incoming[node].clone()));
+                } else {
+                    stack.push((node, true));
+                    stack.extend(incoming[node].iter().map(|(from, _)| (*from, false)));
+                }
+            }
+        }
+        plan
+    }
+}
+
+impl Brain for NeatBrain {
+    fn decide(&mut self, situation: &Situation, _rng: &mut dyn RngCore) -> PlayerActionType {
+        if self.plan.is_none() {
+            self.plan = Some(self.compile());
+        }
+        let mut values = vec![0.0; self.nodes.len()];
+        values[..INPUTS].copy_from_slice(&inputs(situation));
+        for (node, incoming) in self.plan.as_ref().unwrap() {
+            values[*node] = incoming
+                .iter()
+                .map(|(from, weight)| values[*from] * weight)
+                .sum::<f32>()
+                .tanh();
+        }
+
+        let strongest = (0..OUTPUTS).fold(0, |best, output| {
+            if values[INPUTS + output] > values[INPUTS + best] {
+                output
+            } else {
+                best
+            }
+        });
+        PlayerActionType::ALL[strongest]
+    }
+
+    /// NEAT crossover, with `self` taken to be the fitter parent: genes both parents have are inherited
+    /// from either of them at random, the rest only from `self`. A gene disabled in either parent
+    /// is likely to stay disabled.
+    fn crossover(&self, other: &dyn Brain, rng: &mut dyn RngCore) -> Box<dyn Brain> {
+        let Some(other) = other.as_any().downcast_ref::<NeatBrain>() else {
+            return self.box_clone();
+        };
+        let theirs: HashMap<u64, &ConnectionGene> = other
+            .connections
+            .iter()
+            .map(|gene| (gene.innovation, gene))
+            .collect();
+        let connections = self
+            .connections
+            .iter()
+            .map(|mine| match theirs.get(&mine.innovation) {
+                Some(their) => {
+                    let mut gene = if rng.gen_bool(0.5) { *mine } else { **their };
+                    gene.enabled =
+                        (mine.enabled && their.enabled) || !rng.gen_bool(NEAT_KEEP_DISABLED_CHANCE);
+                    gene
+                }
+                None => *mine,
+            })
+            .collect();
+
+        Box::new(Self {
+            nodes: self.nodes.clone(),
+            connections,
+            plan: None,
+        })
+    }
+
+    /// Every weight has a `rate` chance of being nudged, on top of which the network
+    /// may grow a new connection or a new node.
+    fn mutate(&mut self, rate: f32, rng: &mut dyn RngCore) {
+        for gene in self.connections.iter_mut() {
+            if rng.gen::<f32>() < rate {
+                gene.weight += random_gaussian(rng) * NN_MUTATION_STRENGTH;
+            }
+        }
+        if rng.gen_bool(NEAT_ADD_CONNECTION_CHANCE) {
+            self.add_connection(rng);
+        }
+        if rng.gen_bool(NEAT_ADD_NODE_CHANCE) {
+            self.add_node(rng);
+        }
+        self.plan = None;
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        for gene in self.connections.iter() {
+            (gene.innovation, gene.weight.to_bits(), gene.enabled).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Genomes twice the compatibility threshold apart are considered to have nothing in common.
+    fn distance(&self, other: &dyn Brain) -> f32 {
+        match other.as_any().downcast_ref::<NeatBrain>() {
+            Some(other) => {
+                (self.compatibility(other) / (2.0 * NEAT_COMPATIBILITY_THRESHOLD)).min(1.0)
+            }
+            None => 1.0,
+        }
+    }
+
+    fn to_data(&self) -> BrainData {
+        BrainData::Neat(self.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn Brain> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Genomes similar enough to each other to compete among themselves rather than with the whole population,
+/// which gives new topologies a few generations to optimize their weights before they have to measure up.
+#[derive(Debug, Clone)]
+pub struct Species {
+    pub id: u32,
+    representative: NeatBrain,
+    best_fitness: f32,
+    /// For how many generations in a row the species hasn't beaten its best fitness.
+    stagnant_for: u32,
+}
+
+/// The species of NEAT genomes, carried over from one generation to the next.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SpeciesRegistry {
+    pub species: Vec<Species>,
+    next_id: u32,
+}
+
+impl SpeciesRegistry {
+    /// Sort `candidates` (ranked by fitness, best first) into species and breed `count` offspring from them,
+    /// with parents picked within their species by `selection`.
+    /// Fitness is shared within a species, so every species gets offspring in proportion to its average fitness.
+    /// Species that have stagnated for `NEAT_STAGNATION_LIMIT` generations are culled, unless they hold the best genome.
+    pub fn breed(
+        &mut self,
+        candidates: &[(f32, &Genome)],
+        count: usize,
+        selection: &dyn SelectionStrategy,
+        mutation_rate: f32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Genome> {
+        let mut members: Vec<Vec<(f32, &Genome, &NeatBrain)>> = vec![vec![]; self.species.len()];
+        for (fitness, genome) in candidates {
+            let Some(brain) = genome.brain.as_any().downcast_ref::<NeatBrain>() else {
+                continue;
+            };
+            match self.species.iter().position(|species| {
+                species.representative.compatibility(brain) < NEAT_COMPATIBILITY_THRESHOLD
+            }) {
+                Some(index) => members[index].push((*fitness, *genome, brain)),
+                None => {
+                    self.species.push(Species {
+                        id: self.next_id,
+                        representative: brain.clone(),
+                        best_fitness: f32::MIN,
+                        stagnant_for: 0,
+                    });
+                    self.next_id += 1;
+                    members.push(vec![(*fitness, *genome, brain)]);
+                }
+            }
+        }
+
+        for (species, members) in self.species.iter_mut().zip(members.iter()) {
+            let Some((best, _, _)) = members.first() else {
+                continue;
+            };
+            if *best > species.best_fitness {
+                species.best_fitness = *best;
+                species.stagnant_for = 0;
+            } else {
+                species.stagnant_for += 1;
+            }
+            species.representative = members[rng.gen_range(0..members.len())].2.clone();
+        }
+
+        let champion = members
+            .iter()
+            .position(|members| !members.is_empty() && members[0].0 == candidates[0].0);
+        let mut kept = vec![];
+        for (index, (species, members)) in self.species.drain(..).zip(members).enumerate() {
+            if members.is_empty() {
+                continue;
+            }
+            if species.stagnant_for >= NEAT_STAGNATION_LIMIT && Some(index) != champion {
+                info!("Species {} stagnated and was culled.", species.id);
+                continue;
+            }
+            kept.push((species, members));
+        }
+
+        let shares: Vec<f64> = kept
+            .iter()
+            .map(|(_, members)| {
+                members
+                    .iter()
+                    .map(|(fitness, _, _)| fitness.max(0.0) as f64)
+                    .sum::<f64>()
+                    / members.len() as f64
+            })
+            .collect();
+        let offspring_counts = apportion(&shares, count);
+        info!("{} species this generation.", kept.len());
+
+        let mut offspring = vec![];
+        for ((_, members), offspring_count) in kept.iter().zip(offspring_counts) {
+            let scores: Vec<f32> = members.iter().map(|(fitness, _, _)| *fitness).collect();
+            for _ in 0..offspring_count {
+                let mother = selection.select(&scores, rng);
+                let father = selection.select(&scores, rng);
+                let (_, fitter_genome, fitter) = members[mother.min(father)];
+                let (_, other_genome, other) = members[mother.max(father)];
+                let brain = fitter.crossover(other, rng);
+                let mut child = Genome::offspring(brain, fitter_genome, other_genome, rng);
+                child.mutate(mutation_rate, rng);
+                offspring.push(child);
+            }
+        }
+
+        self.species = kept.into_iter().map(|(species, _)| species).collect();
+        offspring
+    }
+}
+
+/// Split `count` between the shares in proportion to them, handing out what's left after rounding down
+/// to the biggest remainders first. Equal shares are used if they're all zero.
+fn apportion(shares: &[f64], count: usize) -> Vec<usize> {
+    let total: f64 = shares.iter().sum();
+    let exact: Vec<f64> = shares
+        .iter()
+        .map(|share| match total > 0.0 {
+            true => share / total * count as f64,
+            false => count as f64 / shares.len() as f64,
+        })
+        .collect();
+    let mut counts: Vec<usize> = exact.iter().map(|exact| *exact as usize).collect();
+
+    let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+    by_remainder.sort_by(|a, b| {
+        (exact[*b] - counts[*b] as f64).total_cmp(&(exact[*a] - counts[*a] as f64))
+    });
+    let handed_out: usize = counts.iter().sum();
+    for index in by_remainder.into_iter().cycle().take(count - handed_out) {
+        counts[index] += 1;
+    }
+    counts
+}
@@ -0,0 +1,216 @@
+use std::any::Any;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::common::{rerror, StableHasher};
+use crate::engine::config::*;
+use crate::engine::random::random_gaussian;
+use crate::simulation::genome::*;
+use crate::simulation::players::*;
+
+/// Inputs: what the player sees within its line of sight, one slot for each of the first `NN_SIGHT` distances
+/// ahead, to the right, behind and to the left of it (see `Situation::seen_towards`, all zeros where it sees no tile)
+/// and what's on the four adjacent tiles (one-hot per tile kind), how well fed and how healthy the player is,
+/// which way it's facing (one-hot), what it remembers seeing last (one-hot kind, how fresh the memory is,
+/// and how far ahead and to the right it was, both clamped to `NN_SIGHT` tiles and scaled to -1..1),
+/// how many players, pieces of food and walls its last scan found (`n / (n + 1)` each), how strong food and pheromones smell where it stands and on the four adjacent tiles
+/// (`s / (s + 1)` each) and a constant bias.
+pub const INPUTS: usize = (SIGHT_SLOTS + 4) * TileKind::COUNT
+    + 2
+    + 4
+    + TileKind::COUNT
+    + 3
+    + IN_SIGHT_KINDS.len()
+    + 2 * SMELLS
+    + 1;
+const SIGHT_SLOTS: usize = NN_SIGHT * RELATIVE_DIRECTIONS.len();
+const IN_SIGHT_KINDS: [TileKind; 3] = [TileKind::Player, TileKind::Food, TileKind::Wall];
+/// Where a player smells things: where it stands and on the four tiles around it.
+const SMELLS: usize = 5;
+/// One output per action, the strongest one wins.
+pub const OUTPUTS: usize = PlayerActionType::ALL.len();
+const WEIGHTS: usize = INPUTS * NN_HIDDEN + (NN_HIDDEN + 1) * OUTPUTS;
+
+/// A small feed-forward neural network with a single `tanh` hidden layer.
+/// The genes are its weights as one flat vector: input to hidden first, then hidden (plus a bias) to output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NnBrain {
+    weights: Vec<f32>,
+}
+
+impl NnBrain {
+    pub fn random(rng: &mut dyn RngCore) -> Self {
+        Self {
+            weights: (0..WEIGHTS).map(|_| random_gaussian(rng)).collect(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        match self.weights.len() == WEIGHTS {
+            true => Ok(()),
+            false => Err(rerror(&format!(
+                "a network has {} weights, not {}",
+                self.weights.len(),
+                WEIGHTS
+            ))),
+        }
+    }
+}
+
+/// What a network brain is fed with, laid out as described for `INPUTS`.
+pub fn inputs(situation: &Situation) -> [f32; INPUTS] {
+    let mut inputs = [0.0; INPUTS];
+    let tiles = (0..NN_SIGHT)
+        .flat_map(|distance| {
+            RELATIVE_DIRECTIONS.map(|direction| situation.seen_towards(distance, direction))
+        })
+        .chain(situation.adjacent.iter().copied().map(Some));
+    for (slot, tile) in tiles.enumerate() {
+        if let Some(tile) = tile {
+            inputs[slot * TileKind::COUNT + tile.index()] = 1.0;
+        }
+    }
+
+    let rest = (SIGHT_SLOTS + 4) * TileKind::COUNT;
+    inputs[rest] = situation.energy_bucket as f32 / (ENERGY_BUCKETS - 1).max(1) as f32;
+    inputs[rest + 1] = situation.health_share;
+    let facing = match situation.facing {
+        FacingDirection::Up => 0,
+        FacingDirection::Right => 1,
+        FacingDirection::Down => 2,
+        FacingDirection::Left => 3,
+    };
+    inputs[rest + 2 + facing] = 1.0;
+
+    if let Some(memory) = situation.memory {
+        let memory_start = rest + 2 + 4;
+        let sight = NN_SIGHT as i32;
+        inputs[memory_start + memory.kind.index()] = 1.0;
+        inputs[memory_start + TileKind::COUNT] = 1.0 / (1.0 + memory.age as f32);
+        inputs[memory_start + TileKind::COUNT + 1] =
+            memory.ahead.clamp(-sight, sight) as f32 / sight as f32;
+        inputs[memory_start + TileKind::COUNT + 2] =
+            memory.right.clamp(-sight, sight) as f32 / sight as f32;
+    }
+
+    let in_sight_start = rest + 2 + 4 + TileKind::COUNT + 3;
+    for (slot, kind) in IN_SIGHT_KINDS.iter().enumerate() {
+        let count = situation.in_sight_count(*kind) as f32;
+        inputs[in_sight_start + slot] = count / (count + 1.0);
+    }
+
+    let smell_start = in_sight_start + IN_SIGHT_KINDS.len();
+    for (layer, smell) in [situation.food_scent, situation.pheromone]
+        .iter()
+        .enumerate()
+    {
+        for (slot, scent) in std::iter::once(smell.here).chain(smell.around).enumerate() {
+            inputs[smell_start + layer * SMELLS + slot] = scent / (scent + 1.0);
+        }
+    }
+    inputs[INPUTS - 1] = 1.0;
+    inputs
+}
+
+impl Brain for NnBrain {
+    fn decide(&mut self, situation: &Situation, _rng: &mut dyn RngCore) -> PlayerActionType {
+        let inputs = inputs(situation);
+        let (to_hidden, to_output) = self.weights.split_at(INPUTS * NN_HIDDEN);
+
+        let hidden: Vec<f32> = to_hidden
+            .chunks(INPUTS)
+            .map(|weights| {
+                weights
+                    .iter()
+                    .zip(inputs.iter())
+                    .map(|(w, i)| w * i)
+                    .sum::<f32>()
+                    .tanh()
+            })
+            .chain(std::iter::once(1.0))
+            .collect();
+        let strongest = to_output
+            .chunks(NN_HIDDEN + 1)
+            .map(|weights| weights.iter().zip(hidden.iter()).map(|(w, h)| w * h).sum())
+            .enumerate()
+            .fold((0, f32::MIN), |best, (output, value)| {
+                if value > best.1 {
+                    (output, value)
+                } else {
+                    best
+                }
+            })
+            .0;
+        PlayerActionType::ALL[strongest]
+    }
+
+    /// Either uniform crossover (every weight is taken from one of the parents)
+    /// or arithmetic crossover (every weight is a blend of both parents' weights), picked at random.
+    fn crossover(&self, other: &dyn Brain, rng: &mut dyn RngCore) -> Box<dyn Brain> {
+        let Some(other) = other.as_any().downcast_ref::<NnBrain>() else {
+            return self.box_clone();
+        };
+        let weights = if rng.gen_bool(0.5) {
+            self.weights
+                .iter()
+                .zip(other.weights.iter())
+                .map(|(mine, theirs)| if rng.gen_bool(0.5) { *mine } else { *theirs })
+                .collect()
+        } else {
+            let blend: f32 = rng.gen();
+            self.weights
+                .iter()
+                .zip(other.weights.iter())
+                .map(|(mine, theirs)| blend * mine + (1.0 - blend) * theirs)
+                .collect()
+        };
+        Box::new(Self { weights })
+    }
+
+    /// Gaussian mutation: every weight has a `rate` chance of being nudged by `NN_MUTATION_STRENGTH` standard deviations.
+    fn mutate(&mut self, rate: f32, rng: &mut dyn RngCore) {
+        for weight in self.weights.iter_mut() {
+            if rng.gen::<f32>() < rate {
+                *weight += random_gaussian(rng) * NN_MUTATION_STRENGTH;
+            }
+        }
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        for weight in self.weights.iter() {
+            weight.to_bits().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// The mean absolute difference between the weights, capped at 1:
+    /// weights are born with a standard deviation of 1, so that's about how far apart two random networks are.
+    fn distance(&self, other: &dyn Brain) -> f32 {
+        let Some(other) = other.as_any().downcast_ref::<NnBrain>() else {
+            return 1.0;
+        };
+        let difference: f32 = self
+            .weights
+            .iter()
+            .zip(other.weights.iter())
+            .map(|(mine, theirs)| (mine - theirs).abs())
+            .sum();
+        (difference / self.weights.len().max(1) as f32).min(1.0)
+    }
+
+    fn to_data(&self) -> BrainData {
+        BrainData::Nn(self.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn Brain> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
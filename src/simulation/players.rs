@@ -1,6 +1,7 @@
 use crate::engine::common::*;
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use std::error::Error;
 
@@ -13,7 +14,7 @@ pub struct Food;
 #[derive(Component, Debug)]
 pub struct Wall;
 
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FacingDirection {
     Up,
     Left,
@@ -21,7 +22,7 @@ pub enum FacingDirection {
     Right,
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PlayerActionType {
     Idle,
     MoveForward,
@@ -32,6 +33,40 @@ pub enum PlayerActionType {
     Kill,
     ScanLOS,
     BuildWall,
+    /// Leave some pheromone behind on the player's own tile, for other players to smell.
+    DepositPheromone,
+}
+
+impl PlayerActionType {
+    /// Every action a player can take, in the order they're listed in reports.
+    pub const ALL: [PlayerActionType; 10] = [
+        PlayerActionType::Idle,
+        PlayerActionType::MoveForward,
+        PlayerActionType::MoveBackwards,
+        PlayerActionType::Turn(FacingDirection::Left),
+        PlayerActionType::Turn(FacingDirection::Right),
+        PlayerActionType::Eat,
+        PlayerActionType::Kill,
+        PlayerActionType::ScanLOS,
+        PlayerActionType::BuildWall,
+        PlayerActionType::DepositPheromone,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerActionType::Idle => "idle",
+            PlayerActionType::MoveForward => "move_forward",
+            PlayerActionType::MoveBackwards => "move_backwards",
+            PlayerActionType::Turn(FacingDirection::Left) => "turn_left",
+            PlayerActionType::Turn(FacingDirection::Right) => "turn_right",
+            PlayerActionType::Turn(_) => "turn",
+            PlayerActionType::Eat => "eat",
+            PlayerActionType::Kill => "kill",
+            PlayerActionType::ScanLOS => "scan_los",
+            PlayerActionType::BuildWall => "build_wall",
+            PlayerActionType::DepositPheromone => "deposit_pheromone",
+        }
+    }
 }
 
 #[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
@@ -51,24 +86,123 @@ impl Energy {
     }
 }
 
+/// How much more damage a player can take. It doesn't come back, and the player dies once it's gone.
+#[derive(Debug)]
+pub struct Health {
+    pub value: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Self { value: max, max }
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Vitals {
     pub energy: Energy,
+    pub health: Health,
     pub status: PlayerStatus,
 }
 
 impl Vitals {
-    pub fn new(energy_value: u32) -> Self {
+    pub fn new(energy_value: u32, health_value: u32) -> Self {
         Self {
             energy: Energy::new(energy_value),
+            health: Health::new(health_value),
             status: PlayerStatus::Alive,
         }
     }
 }
 
+/// What a player has achieved during its generation so far, which is what its fitness is judged on.
+#[derive(Component, Debug, Clone, Copy, Default, Serialize)]
+pub struct PlayerStats {
+    pub turns_survived: u32,
+    /// The player's energy after its last turn, 0 once it's dead.
+    pub energy: u32,
+    /// How many meals the player had, whether it got the whole piece of food or a share of it.
+    pub food_eaten: u32,
+    pub kills: u32,
+    pub walls_built: u32,
+    /// How many times the player took each action, in the order of `PlayerActionType::ALL`.
+    pub actions: [u32; PlayerActionType::ALL.len()],
+}
+
+/// Something a player saw when it scanned its line of sight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sighting {
+    pub seen: OccupantType,
+    /// How many tiles away from the scanner it was, diagonal steps included, 1 being the tiles right next to it.
+    pub distance: u32,
+    pub pos: BoardPosition,
+    /// The turn the scan happened on.
+    pub turn: u32,
+}
+
+/// A player's memory of what it has seen: what its last scan found, kept until a scan finds something else.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Perception {
+    /// The closest thing the scan found.
+    pub last_seen: Option<Sighting>,
+    /// Everything the scan found, closest first. Only ever more than one thing in the `full` scan mode.
+    pub in_sight: Vec<Sighting>,
+}
+
+/// The area a player sees when it scans. Players can inherit it along with their brain, see `SimConfig::evolve_los_shape`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LosShape {
+    /// A single line of tiles straight ahead.
+    Straight,
+    /// Everything ahead within `half_angle` degrees of where the player is facing.
+    Cone,
+    /// Everything around the player, behind it included.
+    Circle,
+}
+
+impl LosShape {
+    pub const ALL: [LosShape; 3] = [LosShape::Straight, LosShape::Cone, LosShape::Circle];
+}
+
+/// How much of what's within the line of sight a scan reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanMode {
+    /// Only the closest thing.
+    First,
+    /// Everything that can be seen, closest first.
+    Full,
+}
+
 #[derive(Component, Debug, Clone, Copy)]
 pub struct LineOfSight {
+    pub shape: LosShape,
+    /// How far the player sees: the length of the line or the cone, or the radius of the circle.
     pub length: u32,
+    /// How wide a cone is, in degrees on either side of where the player is facing.
+    pub half_angle: u32,
+}
+
+impl LineOfSight {
+    /// Whether the tile `ahead` tiles in front of the player and `right` tiles to its right
+    /// (negative for behind and to the left) lies within the line of sight, walls aside.
+    pub fn covers(&self, ahead: i32, right: i32) -> bool {
+        let length = self.length as i32;
+        let within_reach = ahead * ahead + right * right <= length * length;
+        match self.shape {
+            LosShape::Straight => right == 0 && (1..=length).contains(&ahead),
+            LosShape::Cone => {
+                // a little leeway for rounding, so a 45 degree cone takes the diagonals in
+                ahead >= 1
+                    && within_reach
+                    && (right.abs() as f32).atan2(ahead as f32).to_degrees()
+                        <= self.half_angle as f32 + 0.001
+            }
+            LosShape::Circle => (ahead, right) != (0, 0) && within_reach,
+        }
+    }
 }
 
 pub fn position_after_turn(
@@ -0,0 +1,102 @@
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::config::SimConfig;
+
+/// A way of picking parents for the next generation.
+/// Candidates are given as their scores, ranked best first, and a parent is picked by its index in that ranking.
+pub trait SelectionStrategy {
+    fn select(&self, ranked: &[f32], rng: &mut dyn RngCore) -> usize;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelectionKind {
+    Truncation,
+    Tournament,
+    Roulette,
+    Rank,
+}
+
+impl SelectionKind {
+    pub fn strategy(&self, config: &SimConfig) -> Box<dyn SelectionStrategy> {
+        match self {
+            SelectionKind::Truncation => Box::new(Truncation {
+                ratio: config.parent_ratio(),
+            }),
+            SelectionKind::Tournament => Box::new(Tournament {
+                size: config.tournament_size,
+            }),
+            SelectionKind::Roulette => Box::new(Roulette),
+            SelectionKind::Rank => Box::new(Rank),
+        }
+    }
+}
+
+/// Only the best `ratio` of the candidates (but at least two of them, if there are two) get to be parents,
+/// all with the same chances.
+pub struct Truncation {
+    pub ratio: f32,
+}
+
+impl SelectionStrategy for Truncation {
+    fn select(&self, ranked: &[f32], rng: &mut dyn RngCore) -> usize {
+        let parent_count = ((ranked.len() as f32 * self.ratio).ceil() as usize)
+            .clamp(ranked.len().min(2), ranked.len());
+        rng.gen_range(0..parent_count)
+    }
+}
+
+/// `size` candidates are drawn at random and the best of them wins.
+pub struct Tournament {
+    pub size: u32,
+}
+
+impl SelectionStrategy for Tournament {
+    fn select(&self, ranked: &[f32], rng: &mut dyn RngCore) -> usize {
+        (0..self.size.max(1))
+            .map(|_| rng.gen_range(0..ranked.len()))
+            .min()
+            .unwrap()
+    }
+}
+
+/// Fitness-proportionate: the chances of a candidate are its share of the total score, with negative scores
+/// counting as 0. If nobody scored anything, everybody has the same chances.
+pub struct Roulette;
+
+impl SelectionStrategy for Roulette {
+    fn select(&self, ranked: &[f32], rng: &mut dyn RngCore) -> usize {
+        let weights: Vec<f64> = ranked.iter().map(|score| score.max(0.0) as f64).collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return rng.gen_range(0..ranked.len());
+        }
+        pick_weighted(&weights, total, rng)
+    }
+}
+
+/// The chances of a candidate only depend on its place in the ranking: the best of `n` candidates
+/// is `n` times as likely to be picked as the worst one, however close their scores are.
+pub struct Rank;
+
+impl SelectionStrategy for Rank {
+    fn select(&self, ranked: &[f32], rng: &mut dyn RngCore) -> usize {
+        let count = ranked.len();
+        let weights: Vec<f64> = (1..=count).rev().map(|weight| weight as f64).collect();
+        pick_weighted(&weights, (count * (count + 1) / 2) as f64, rng)
+    }
+}
+
+/// The index of a weight picked with the chances of `weight / total`.
+fn pick_weighted(weights: &[f64], total: f64, rng: &mut dyn RngCore) -> usize {
+    let mut ticket = rng.gen_range(0.0..total);
+    for (index, weight) in weights.iter().enumerate() {
+        if ticket < *weight {
+            return index;
+        }
+        ticket -= weight;
+    }
+    // rounding errors can leave the ticket just past the last weight
+    weights.iter().rposition(|weight| *weight > 0.0).unwrap()
+}